@@ -0,0 +1,214 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+use crate::catalog::{catalog, StepKind};
+use crate::InstallConfig;
+
+/// Value accepted in an answer file to explicitly skip a single-choice step.
+const SKIP: &str = "skip";
+
+/// A declarative answer file (TOML or JSON) for unattended installs.
+///
+/// Every field is optional: anything left out is asked interactively.
+/// Single-choice steps accept the id of one of the step's catalog
+/// components, or `"skip"` to leave the component out.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct AnswerFile {
+    aur_helper: Option<String>,
+    greeter: Option<String>,
+    gpu_driver: Option<String>,
+    hybrid_graphics: Option<bool>,
+    hyprland_version: Option<String>,
+    xdg_user_dirs: Option<bool>,
+    uwsm: Option<bool>,
+    terminal: Option<String>,
+    shell: Option<String>,
+    notification_daemon: Option<String>,
+    audio: Option<String>,
+    xdg_portal: Option<bool>,
+    auth_agent: Option<String>,
+    qt_support: Option<bool>,
+    status_bar: Option<String>,
+    wallpaper_utils: Option<Vec<String>>,
+    app_launcher: Option<String>,
+    prime_run_keybinding: Option<bool>,
+    color_picker: Option<String>,
+    clipboard_manager: Option<String>,
+    gui_file_manager: Option<String>,
+    tui_file_manager: Option<String>,
+}
+
+impl AnswerFile {
+    /// Loads and validates an answer file. The format is picked from the
+    /// extension: `.json` is parsed as JSON, anything else as TOML.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+        let answers: AnswerFile = if path.extension().is_some_and(|ext| ext == "json") {
+            serde_json::from_str(&content).map_err(|e| format!("{}: {}", path.display(), e))?
+        } else {
+            toml::from_str(&content).map_err(|e| format!("{}: {}", path.display(), e))?
+        };
+
+        answers.validate()?;
+        Ok(answers)
+    }
+
+    /// Builds an answer file that answers every step exactly as `config` does.
+    pub fn from_config(config: &InstallConfig) -> Self {
+        let single = |key: &str| Some(config.selection(key).first().cloned().unwrap_or_else(|| SKIP.to_string()));
+        let toggle = |key: &str| Some(!config.selection(key).is_empty());
+
+        AnswerFile {
+            aur_helper: single("aur_helper"),
+            greeter: single("greeter"),
+            gpu_driver: single("gpu_driver"),
+            hybrid_graphics: toggle("hybrid_graphics"),
+            hyprland_version: single("hyprland_version"),
+            xdg_user_dirs: toggle("xdg_user_dirs"),
+            uwsm: toggle("uwsm"),
+            terminal: single("terminal"),
+            shell: single("shell"),
+            notification_daemon: single("notification_daemon"),
+            audio: single("audio"),
+            xdg_portal: toggle("xdg_portal"),
+            auth_agent: single("auth_agent"),
+            qt_support: toggle("qt_support"),
+            status_bar: single("status_bar"),
+            wallpaper_utils: Some(config.selection("wallpaper_utils")),
+            app_launcher: single("app_launcher"),
+            prime_run_keybinding: toggle("prime_run_keybinding"),
+            color_picker: single("color_picker"),
+            clipboard_manager: single("clipboard_manager"),
+            gui_file_manager: single("gui_file_manager"),
+            tui_file_manager: single("tui_file_manager"),
+        }
+    }
+
+    /// Writes the answer file, as JSON for a `.json` extension and TOML otherwise.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let content = if path.extension().is_some_and(|ext| ext == "json") {
+            serde_json::to_string_pretty(self).map_err(|e| e.to_string())? + "\n"
+        } else {
+            toml::to_string(self).map_err(|e| e.to_string())?
+        };
+
+        fs::write(path, content).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    /// Whether the answer file covers the step with this key.
+    pub fn is_answered(&self, key: &str) -> bool {
+        self.entries().iter().any(|(k, _)| k == key)
+    }
+
+    /// The answered steps as (step key, value) pairs.
+    fn entries(&self) -> Vec<(String, Value)> {
+        match serde_json::to_value(self) {
+            Ok(Value::Object(map)) => map.into_iter().filter(|(_, v)| !v.is_null()).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Checks every answer against the components its step offers.
+    pub fn validate(&self) -> Result<(), String> {
+        for (key, value) in self.entries() {
+            let step = catalog()
+                .step(&key)
+                .ok_or_else(|| format!("Unknown step \"{}\"", key))?;
+
+            let values: Vec<&str> = match value {
+                Value::String(ref value) if value.eq_ignore_ascii_case(SKIP) => Vec::new(),
+                Value::String(ref value) => vec![value.as_str()],
+                Value::Array(ref values) => values.iter().filter_map(|v| v.as_str()).collect(),
+                _ => Vec::new(),
+            };
+
+            for value in values {
+                if catalog().component(&key, value).is_none() {
+                    let valid: Vec<&str> = catalog().components_for(&key).map(|c| c.id.as_str()).collect();
+                    let expected = match step.kind {
+                        StepKind::Multi => format!("any of: {}", valid.join(", ")),
+                        _ => format!("one of: {}, {}", valid.join(", "), SKIP),
+                    };
+                    return Err(format!("Invalid value \"{}\" for {} (expected {})", value, key, expected));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// A config with every answered step filled in.
+    pub fn config(&self) -> InstallConfig {
+        let mut config = InstallConfig::default();
+        self.apply(&mut config);
+        config
+    }
+
+    /// Copies every answered step into `config`.
+    pub fn apply(&self, config: &mut InstallConfig) {
+        for (key, value) in self.entries() {
+            let ids = match value {
+                Value::String(value) if value.eq_ignore_ascii_case(SKIP) => Vec::new(),
+                Value::String(value) => vec![value],
+                Value::Array(values) => values.into_iter().filter_map(|v| v.as_str().map(str::to_string)).collect(),
+                Value::Bool(true) => catalog().components_for(&key).take(1).map(|c| c.id.clone()).collect(),
+                _ => Vec::new(),
+            };
+            config.set_selection(&key, ids);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A config choosing the last component of every single-choice step,
+    /// every component of multi-choice steps and every toggle.
+    fn full_config() -> InstallConfig {
+        let mut config = InstallConfig::default();
+        for step in &catalog().steps {
+            let ids: Vec<String> = catalog().components_for(&step.key).map(|c| c.id.clone()).collect();
+            let ids = match step.kind {
+                StepKind::Multi => ids,
+                StepKind::Single | StepKind::Toggle => ids.into_iter().last().into_iter().collect(),
+            };
+            config.set_selection(&step.key, ids);
+        }
+        config
+    }
+
+    fn assert_round_trip(config: &InstallConfig, name: &str) {
+        let dir = std::env::temp_dir().join(format!("hyprland-installer-answers-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let answers = AnswerFile::from_config(config);
+        for file in ["answers.toml", "answers.json"] {
+            let path = dir.join(file);
+            answers.save(&path).unwrap();
+            let loaded = AnswerFile::load(&path).unwrap().config();
+
+            for step in &catalog().steps {
+                assert!(answers.is_answered(&step.key), "{} is not answered", step.key);
+                assert_eq!(loaded.selection(&step.key), config.selection(&step.key), "{} in {}", step.key, file);
+            }
+        }
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn config_round_trips_through_saved_answers() {
+        assert_round_trip(&full_config(), "full");
+    }
+
+    #[test]
+    fn empty_config_round_trips_as_skipped_steps() {
+        assert_round_trip(&InstallConfig::default(), "empty");
+    }
+}
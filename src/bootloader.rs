@@ -0,0 +1,349 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::mkinitcpio;
+use crate::plan::{FileWrite, InstallPlan, SystemCommand};
+
+const GRUB_DEFAULTS: &str = "/etc/default/grub";
+const GRUB_CONFIG: &str = "/boot/grub/grub.cfg";
+const GRUB_CMDLINE_KEY: &str = "GRUB_CMDLINE_LINUX_DEFAULT";
+/// Parameters for every boot entry; only cleaned up, never added to
+const GRUB_CMDLINE_LINUX_KEY: &str = "GRUB_CMDLINE_LINUX";
+
+/// systemd-boot entry directories, depending on where the ESP is mounted.
+const LOADER_ENTRY_DIRS: [&str; 2] = ["/boot/loader/entries", "/efi/loader/entries"];
+
+/// Command line baked into unified kernel images by mkinitcpio.
+const UKI_CMDLINE: &str = "/etc/kernel/cmdline";
+/// Drop-in directory for UKI command lines; preferred when it exists.
+const UKI_CMDLINE_DIR: &str = "/etc/cmdline.d";
+const UKI_CMDLINE_DROP_IN: &str = "hyprland-installer.conf";
+
+/// Kernel parameters the chosen components want set or cleared.
+#[derive(Debug, Default, Clone)]
+pub struct ParamChanges {
+    /// `key=value` or bare flags; a different value for the same key is replaced
+    pub add: Vec<String>,
+    /// A bare key removes it with any value; `key=value` only that exact one
+    pub remove: Vec<String>,
+}
+
+impl ParamChanges {
+    pub fn is_empty(&self) -> bool {
+        self.add.is_empty() && self.remove.is_empty()
+    }
+}
+
+fn param_key(param: &str) -> &str {
+    param.split('=').next().unwrap_or(param)
+}
+
+/// Applies `changes` to a space-separated kernel command line. Returns
+/// `None` when the command line already has the wanted parameters.
+pub fn edit_cmdline(cmdline: &str, changes: &ParamChanges) -> Option<String> {
+    let before: Vec<&str> = cmdline.split_whitespace().collect();
+
+    let removed = |param: &str| {
+        changes.remove.iter().any(|r| if r.contains('=') { r == param } else { r == param_key(param) })
+    };
+    let mut params: Vec<String> = before.iter().filter(|p| !removed(p)).map(|p| p.to_string()).collect();
+
+    for add in &changes.add {
+        match params.iter().position(|p| param_key(p) == param_key(add)) {
+            Some(index) => params[index] = add.clone(),
+            None => params.push(add.clone()),
+        }
+    }
+
+    if params.iter().map(String::as_str).eq(before.iter().copied()) {
+        None
+    } else {
+        Some(params.join(" "))
+    }
+}
+
+/// Describes the difference between two command lines, e.g.
+/// "+nvidia_drm.modeset=1 -nomodeset".
+fn describe(before: &str, after: &str) -> String {
+    let old: Vec<&str> = before.split_whitespace().collect();
+    let new: Vec<&str> = after.split_whitespace().collect();
+    let added = new.iter().filter(|p| !old.contains(p)).map(|p| format!("+{}", p));
+    let removed = old.iter().filter(|p| !new.contains(p)).map(|p| format!("-{}", p));
+    added.chain(removed).collect::<Vec<_>>().join(" ")
+}
+
+fn file_write(path: PathBuf, content: String, description: String) -> FileWrite {
+    let backup = path.with_extension(match path.extension() {
+        Some(ext) => format!("{}.backup", ext.to_string_lossy()),
+        None => "backup".to_string(),
+    });
+    FileWrite {
+        path,
+        content,
+        backup: Some(backup),
+        owner: None,
+        description: format!("kernel parameters: {}", description),
+        note: None,
+    }
+}
+
+/// Applies `changes` to the contents of `/etc/default/grub`. Parameters are
+/// added to `GRUB_CMDLINE_LINUX_DEFAULT` and removed from it as well as from
+/// `GRUB_CMDLINE_LINUX`. Returns the new contents and a description of the
+/// change, or `None` when nothing changes.
+fn edit_grub_defaults(content: &str, changes: &ParamChanges) -> Option<(String, String)> {
+    let removals = ParamChanges { add: Vec::new(), remove: changes.remove.clone() };
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+    let mut before: Vec<String> = Vec::new();
+    let mut after: Vec<String> = Vec::new();
+
+    for (key, key_changes) in [(GRUB_CMDLINE_KEY, changes), (GRUB_CMDLINE_LINUX_KEY, &removals)] {
+        let prefix = format!("{}=", key);
+        let index = lines.iter().position(|l| l.trim_start().starts_with(&prefix));
+        let current = index
+            .map(|i| lines[i].trim_start()[prefix.len()..].trim().trim_matches(['"', '\'']).to_string())
+            .unwrap_or_default();
+
+        if let Some(updated) = edit_cmdline(&current, key_changes) {
+            let line = format!("{}\"{}\"", prefix, updated);
+            match index {
+                Some(i) => lines[i] = line,
+                None => lines.push(line),
+            }
+            before.push(current);
+            after.push(updated);
+        }
+    }
+
+    if before.is_empty() {
+        return None;
+    }
+    Some((lines.join("\n") + "\n", describe(&before.join(" "), &after.join(" "))))
+}
+
+/// Plans the edit of `/etc/default/grub` and the `grub-mkconfig` run.
+fn plan_grub(plan: &mut InstallPlan, changes: &ParamChanges) -> bool {
+    let content = match fs::read_to_string(GRUB_DEFAULTS) {
+        Ok(content) => content,
+        Err(_) => return false,
+    };
+
+    if let Some((updated, description)) = edit_grub_defaults(&content, changes) {
+        plan.file_writes.push(file_write(GRUB_DEFAULTS.into(), updated, description));
+        plan.system_commands.push(regenerate_grub());
+    }
+    true
+}
+
+/// The command that rebuilds `grub.cfg` from `/etc/default/grub`.
+pub fn regenerate_grub() -> SystemCommand {
+    SystemCommand {
+        argv: vec!["grub-mkconfig".to_string(), "-o".to_string(), GRUB_CONFIG.to_string()],
+        description: "Regenerating the GRUB config".to_string(),
+    }
+}
+
+/// Applies `changes` to the `options` of a systemd-boot entry. Only entries
+/// that boot a kernel (`linux` or `uki`) and already have an `options` line
+/// are edited; chain-load entries such as Windows or the EFI shell are left
+/// alone. Returns the new contents and a description of the change.
+fn edit_loader_entry(content: &str, changes: &ParamChanges) -> Option<(String, String)> {
+    let key = |l: &str| l.split_whitespace().next().map(str::to_string);
+    let is_options = |l: &str| key(l).as_deref() == Some("options");
+
+    let boots_kernel = content.lines().any(|l| matches!(key(l).as_deref(), Some("linux" | "uki")));
+    if !boots_kernel || !content.lines().any(is_options) {
+        return None;
+    }
+
+    // An entry may spread its options over several lines; they are merged into one
+    let current: Vec<&str> = content
+        .lines()
+        .filter(|l| is_options(l))
+        .flat_map(|l| l.split_whitespace().skip(1))
+        .collect();
+    let current = current.join(" ");
+    let updated = edit_cmdline(&current, changes)?;
+
+    let mut lines: Vec<String> = Vec::new();
+    let mut written = false;
+    for line in content.lines() {
+        if is_options(line) {
+            if !written {
+                lines.push(format!("options {}", updated));
+                written = true;
+            }
+        } else {
+            lines.push(line.to_string());
+        }
+    }
+
+    Some((lines.join("\n") + "\n", describe(&current, &updated)))
+}
+
+/// Plans the edit of the `options` line of every systemd-boot entry.
+fn plan_systemd_boot(plan: &mut InstallPlan, changes: &ParamChanges) -> bool {
+    let mut entries: Vec<PathBuf> = LOADER_ENTRY_DIRS
+        .iter()
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flat_map(|entries| entries.filter_map(|e| e.ok()).map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|ext| ext == "conf"))
+        .collect();
+    entries.sort();
+
+    for path in &entries {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(_) => continue,
+        };
+
+        if let Some((updated, description)) = edit_loader_entry(&content, changes) {
+            plan.file_writes.push(file_write(path.clone(), updated, description));
+        }
+    }
+
+    !entries.is_empty()
+}
+
+/// Plans the edit of the UKI command line and the `mkinitcpio -P` that
+/// rebuilds the images. A drop-in is used when `/etc/cmdline.d` exists.
+fn plan_uki(plan: &mut InstallPlan, changes: &ParamChanges) -> bool {
+    let drop_in_dir = Path::new(UKI_CMDLINE_DIR);
+    let path = if drop_in_dir.is_dir() {
+        drop_in_dir.join(UKI_CMDLINE_DROP_IN)
+    } else if Path::new(UKI_CMDLINE).exists() {
+        PathBuf::from(UKI_CMDLINE)
+    } else {
+        return false;
+    };
+
+    let current = fs::read_to_string(&path).unwrap_or_default();
+    let current = current.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    if let Some(updated) = edit_cmdline(&current, changes) {
+        let mut write = file_write(path.clone(), updated.clone() + "\n", describe(&current, &updated));
+        if !path.exists() {
+            write.backup = None;
+        }
+        plan.file_writes.push(write);
+        mkinitcpio::regenerate(plan);
+    }
+    true
+}
+
+/// The commands that rebuild boot files generated from `paths`, for when
+/// they change outside a plan (e.g. a rollback restored them):
+/// `grub-mkconfig` for the GRUB defaults, `mkinitcpio -P` for initramfs
+/// configs and UKI command lines.
+pub fn regeneration_commands(paths: &[&Path]) -> Vec<SystemCommand> {
+    let mut commands = Vec::new();
+    if paths.contains(&Path::new(GRUB_DEFAULTS)) {
+        commands.push(regenerate_grub());
+    }
+    let uki = |path: &Path| path == Path::new(UKI_CMDLINE) || path.starts_with(UKI_CMDLINE_DIR);
+    if paths.iter().any(|path| uki(path) || mkinitcpio::affects_initramfs(path)) {
+        commands.push(mkinitcpio::regenerate_command());
+    }
+    commands
+}
+
+/// Plans the kernel parameter changes for every bootloader found: GRUB,
+/// systemd-boot entries and UKI command lines.
+pub fn plan_changes(plan: &mut InstallPlan, changes: &ParamChanges) {
+    if changes.is_empty() {
+        return;
+    }
+
+    let found = [
+        plan_grub(plan, changes),
+        plan_systemd_boot(plan, changes),
+        plan_uki(plan, changes),
+    ];
+
+    if !found.contains(&true) {
+        let mut params: Vec<String> = changes.add.clone();
+        params.extend(changes.remove.iter().map(|r| format!("-{}", r)));
+        plan.notes.push(format!(
+            "No supported bootloader found; set these kernel parameters by hand: {}",
+            params.join(" ")
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn changes(add: &[&str], remove: &[&str]) -> ParamChanges {
+        ParamChanges {
+            add: add.iter().map(|s| s.to_string()).collect(),
+            remove: remove.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn edit_cmdline_replaces_the_same_key() {
+        let updated = edit_cmdline("quiet nvidia_drm.modeset=0 splash", &changes(&["nvidia_drm.modeset=1"], &[]));
+        assert_eq!(updated.as_deref(), Some("quiet nvidia_drm.modeset=1 splash"));
+    }
+
+    #[test]
+    fn edit_cmdline_removes_a_bare_key_with_any_value() {
+        let updated = edit_cmdline("quiet nomodeset=1 splash nomodeset", &changes(&[], &["nomodeset"]));
+        assert_eq!(updated.as_deref(), Some("quiet splash"));
+    }
+
+    #[test]
+    fn edit_cmdline_removes_only_the_exact_key_value() {
+        let updated = edit_cmdline("ibt=off ibt=on", &changes(&[], &["ibt=off"]));
+        assert_eq!(updated.as_deref(), Some("ibt=on"));
+    }
+
+    #[test]
+    fn edit_cmdline_returns_none_when_nothing_changes() {
+        let wanted = changes(&["nvidia_drm.modeset=1"], &["nomodeset"]);
+        assert_eq!(edit_cmdline("quiet nvidia_drm.modeset=1", &wanted), None);
+        assert_eq!(edit_cmdline("", &changes(&[], &["nomodeset"])), None);
+    }
+
+    #[test]
+    fn grub_defaults_get_params_added_and_removed_from_both_keys() {
+        let content = "GRUB_TIMEOUT=5\nGRUB_CMDLINE_LINUX_DEFAULT=\"quiet nomodeset\"\nGRUB_CMDLINE_LINUX=\"nomodeset rw\"\n";
+        let (updated, _) = edit_grub_defaults(content, &changes(&["nvidia_drm.modeset=1"], &["nomodeset"])).unwrap();
+        assert_eq!(
+            updated,
+            "GRUB_TIMEOUT=5\nGRUB_CMDLINE_LINUX_DEFAULT=\"quiet nvidia_drm.modeset=1\"\nGRUB_CMDLINE_LINUX=\"rw\"\n"
+        );
+        assert_eq!(edit_grub_defaults(&updated, &changes(&["nvidia_drm.modeset=1"], &["nomodeset"])), None);
+    }
+
+    #[test]
+    fn grub_defaults_without_the_default_key_get_it_appended() {
+        let content = "GRUB_TIMEOUT=5\n#GRUB_CMDLINE_LINUX_DEFAULT=\"quiet\"\n";
+        let (updated, _) = edit_grub_defaults(content, &changes(&["nvidia_drm.modeset=1"], &[])).unwrap();
+        assert_eq!(
+            updated,
+            "GRUB_TIMEOUT=5\n#GRUB_CMDLINE_LINUX_DEFAULT=\"quiet\"\nGRUB_CMDLINE_LINUX_DEFAULT=\"nvidia_drm.modeset=1\"\n"
+        );
+    }
+
+    #[test]
+    fn loader_entry_options_are_merged_and_edited() {
+        let content = "title Arch\nlinux /vmlinuz-linux\noptions root=/dev/sda2\noptions rw nomodeset\n";
+        let (updated, _) = edit_loader_entry(content, &changes(&["nvidia_drm.modeset=1"], &["nomodeset"])).unwrap();
+        assert_eq!(updated, "title Arch\nlinux /vmlinuz-linux\noptions root=/dev/sda2 rw nvidia_drm.modeset=1\n");
+    }
+
+    #[test]
+    fn loader_entry_without_options_is_left_alone() {
+        let wanted = changes(&["nvidia_drm.modeset=1"], &[]);
+        assert_eq!(edit_loader_entry("title Arch\nlinux /vmlinuz-linux\n", &wanted), None);
+        assert_eq!(edit_loader_entry("title Windows\nefi /EFI/Microsoft/Boot/bootmgfw.efi\n", &wanted), None);
+    }
+
+    #[test]
+    fn chain_load_entry_with_options_is_left_alone() {
+        let content = "title EFI Shell\nefi /shellx64.efi\noptions -nostartup\n";
+        assert_eq!(edit_loader_entry(content, &changes(&["nvidia_drm.modeset=1"], &[])), None);
+    }
+}
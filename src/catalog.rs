@@ -0,0 +1,302 @@
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::pacman;
+
+/// The catalog shipped with the installer.
+const EMBEDDED_CATALOG: &str = include_str!("catalog.toml");
+
+/// System-wide directory of extra component definitions.
+const SYSTEM_COMPONENTS_DIR: &str = "/etc/hyprland-installer/components.d";
+
+/// Every wizard step and every component that can be chosen in it.
+///
+/// `env`, `exec_once`, `binds` and `user_commands` may use the placeholders
+/// `{hypr_dir}`, `{launcher}`, `{igpu_card}`/`{dgpu_card}` and
+/// `{igpu_link}`/`{dgpu_link}`; a line whose placeholder cannot be filled in
+/// is left out.
+/// Prompts, answer file validation, the install plan and the generated
+/// hyprland.conf section are all derived from this.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Catalog {
+    #[serde(rename = "step")]
+    pub steps: Vec<Step>,
+    #[serde(rename = "component", default)]
+    pub components: Vec<Component>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StepKind {
+    /// Pick one component, or skip the step
+    Single,
+    /// Pick any number of components
+    Multi,
+    /// Yes/no for the step's only component
+    Toggle,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Step {
+    /// Field in `InstallConfig` and key in answer files
+    pub key: String,
+    /// Step number shown in the header; consecutive steps may share one
+    pub number: u32,
+    pub title: String,
+    /// Row label in the installation summary
+    pub name: String,
+    pub prompt: String,
+    pub kind: StepKind,
+    pub description: Option<String>,
+    pub skip_label: Option<String>,
+    /// Initial answer for toggle steps
+    #[serde(default)]
+    pub default_on: bool,
+    /// Comment above this step's lines in hyprland.conf
+    pub config_comment: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Component {
+    pub step: String,
+    /// Value stored in `InstallConfig` and answer files
+    pub id: String,
+    /// Shown in prompts instead of the id
+    pub label: Option<String>,
+    /// Hint such as "default" or "recommended" shown next to the label
+    pub tag: Option<String>,
+    /// One-line summary shown in prompts; falls back to the package's own
+    pub description: Option<String>,
+    #[serde(default)]
+    pub packages: Vec<String>,
+    #[serde(default)]
+    pub aur_packages: Vec<String>,
+    #[serde(default)]
+    pub services: Vec<String>,
+    #[serde(default)]
+    pub env: Vec<String>,
+    #[serde(default)]
+    pub exec_once: Vec<String>,
+    /// Keybindings, written as `bind = ...`
+    #[serde(default)]
+    pub binds: Vec<String>,
+    /// How to open this component, for launchers (fills `{launcher}`)
+    pub launch_command: Option<String>,
+    #[serde(default)]
+    pub user_commands: Vec<String>,
+    /// Kernel modules to load from the initramfs
+    #[serde(default)]
+    pub mkinitcpio_modules: Vec<String>,
+    /// mkinitcpio hooks to add, in front of `block`
+    #[serde(default)]
+    pub mkinitcpio_hooks: Vec<String>,
+    /// mkinitcpio hooks that must not run, e.g. `kms` for NVIDIA
+    #[serde(default)]
+    pub mkinitcpio_remove_hooks: Vec<String>,
+    /// Kernel parameters to set, e.g. `nvidia_drm.modeset=1`
+    #[serde(default)]
+    pub kernel_params: Vec<String>,
+    /// Kernel parameters to clear; a bare key matches any value
+    #[serde(default)]
+    pub remove_kernel_params: Vec<String>,
+    pub notes: Option<String>,
+    /// Overrides the step's comment above this component's hyprland.conf lines
+    pub config_comment: Option<String>,
+}
+
+/// A `components.d` file: extra components for the existing steps.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ComponentFile {
+    #[serde(rename = "component", default)]
+    components: Vec<Component>,
+}
+
+impl Catalog {
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let catalog: Catalog = toml::from_str(content).map_err(|e| e.to_string())?;
+        catalog.validate()?;
+        Ok(catalog)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        for component in &self.components {
+            if self.step(&component.step).is_none() {
+                return Err(format!("Component \"{}\" refers to unknown step \"{}\"", component.id, component.step));
+            }
+        }
+
+        for step in &self.steps {
+            let count = self.components_for(&step.key).count();
+            if step.kind == StepKind::Toggle && count != 1 {
+                return Err(format!("Toggle step \"{}\" needs exactly one component, found {}", step.key, count));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Adds the components from a `components.d` file. A component with the
+    /// same step and id as an existing one replaces it.
+    fn merge(&mut self, content: &str) -> Result<usize, String> {
+        let file: ComponentFile = toml::from_str(content).map_err(|e| e.to_string())?;
+        let count = file.components.len();
+
+        for component in file.components {
+            match self.step(&component.step) {
+                None => {
+                    return Err(format!("Component \"{}\" refers to unknown step \"{}\"", component.id, component.step));
+                }
+                Some(step) if step.kind == StepKind::Toggle => {
+                    return Err(format!("Step \"{}\" is a yes/no step and cannot take extra components", step.key));
+                }
+                Some(_) => {}
+            }
+
+            match self.components.iter().position(|c| c.step == component.step && c.id == component.id) {
+                Some(index) => self.components[index] = component,
+                None => self.components.push(component),
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Merges every `*.toml` file in `dir`, in file name order. A file that
+    /// fails to parse is reported and skipped without affecting the others.
+    fn load_dir(&mut self, dir: &Path) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|ext| ext == "toml"))
+            .collect();
+        paths.sort();
+
+        for path in paths {
+            let mut candidate = self.clone();
+            let result = fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|content| candidate.merge(&content));
+
+            match result {
+                Ok(count) => {
+                    println!("✓ Loaded {} component(s) from {}", count, path.display());
+                    *self = candidate;
+                }
+                Err(e) => eprintln!("⚠️  Skipping {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    pub fn step(&self, key: &str) -> Option<&Step> {
+        self.steps.iter().find(|s| s.key == key)
+    }
+
+    pub fn components_for<'a>(&'a self, step: &'a str) -> impl Iterator<Item = &'a Component> + 'a {
+        self.components.iter().filter(move |c| c.step == step)
+    }
+
+    pub fn component<'a>(&'a self, step: &'a str, id: &str) -> Option<&'a Component> {
+        self.components_for(step).find(|c| c.id == id)
+    }
+}
+
+impl Component {
+    /// The text shown for this component in prompts, e.g. "sddm (default)".
+    pub fn display_label(&self) -> String {
+        let label = self.label.as_deref().unwrap_or(&self.id);
+        match self.tag {
+            Some(ref tag) => format!("{} ({})", label, tag),
+            None => label.to_string(),
+        }
+    }
+
+    fn all_packages(&self) -> impl Iterator<Item = &String> {
+        self.packages.iter().chain(&self.aur_packages)
+    }
+
+    /// Whether the component's main package, the first one it lists, is
+    /// already installed. Components without packages never are.
+    pub fn is_installed(&self) -> bool {
+        self.all_packages().next().is_some_and(|name| pacman::local_db().get(name).is_some())
+    }
+
+    /// Where the packages come from: "repo", "AUR" or "repo + AUR". Without
+    /// a sync database the catalog's split is trusted.
+    pub fn origin(&self) -> Option<String> {
+        let db = pacman::sync_db();
+        let mut origins: Vec<&str> = Vec::new();
+
+        for name in self.all_packages() {
+            let in_repo = if db.is_empty() { self.packages.contains(name) } else { db.find(name).is_some() };
+            let origin = if in_repo { "repo" } else { "AUR" };
+            if !origins.contains(&origin) {
+                origins.push(origin);
+            }
+        }
+
+        (!origins.is_empty()).then(|| origins.join(" + "))
+    }
+
+    /// The text shown for this component in prompts, with where it comes
+    /// from, whether it is installed and what it is, e.g.
+    /// "dunst (default) [repo] (installed) — Lightweight notification daemon".
+    pub fn prompt_label(&self) -> String {
+        let mut label = self.display_label();
+        if let Some(origin) = self.origin() {
+            label.push_str(&format!(" [{}]", origin));
+        }
+        if self.is_installed() {
+            label.push_str(" (installed)");
+        }
+
+        let description = self.description.clone().or_else(|| {
+            let package = self.all_packages().next()?;
+            pacman::sync_db().find(package).map(|p| p.description.clone()).filter(|d| !d.is_empty())
+        });
+        if let Some(description) = description {
+            label.push_str(&format!(" — {}", description));
+        }
+        label
+    }
+}
+
+impl Step {
+    pub fn skip_label(&self) -> &str {
+        self.skip_label.as_deref().unwrap_or("SKIP")
+    }
+}
+
+static CATALOG: OnceLock<Catalog> = OnceLock::new();
+
+/// Directories searched for extra component definitions. The user's
+/// directory comes last so its entries override the system-wide ones.
+pub fn components_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![PathBuf::from(SYSTEM_COMPONENTS_DIR)];
+    if let Some(config_dir) = crate::get_user_config_dir() {
+        dirs.push(config_dir.join("hyprland-installer/components.d"));
+    }
+    dirs
+}
+
+/// The component catalog for this run: the embedded catalog plus any
+/// components from the `components.d` directories.
+pub fn catalog() -> &'static Catalog {
+    CATALOG.get_or_init(|| {
+        let mut catalog = Catalog::parse(EMBEDDED_CATALOG).expect("embedded catalog.toml is invalid");
+        for dir in components_dirs() {
+            catalog.load_dir(&dir);
+        }
+        catalog
+    })
+}
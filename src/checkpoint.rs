@@ -0,0 +1,144 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::answers::AnswerFile;
+use crate::cli::options;
+use crate::plan::InstallPlan;
+use crate::rollback::{self, RunRecord, STATE_DIR};
+use crate::InstallConfig;
+
+const CHECKPOINT_FILE: &str = "checkpoint.json";
+
+/// The phases of an installation, in the order they run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    AurHelper,
+    RepoPackages,
+    AurPackages,
+    Services,
+    UserCommands,
+    FileWrites,
+    SystemCommands,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 7] = [
+        Phase::AurHelper,
+        Phase::RepoPackages,
+        Phase::AurPackages,
+        Phase::Services,
+        Phase::UserCommands,
+        Phase::FileWrites,
+        Phase::SystemCommands,
+    ];
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Phase::AurHelper => "AUR helper",
+            Phase::RepoPackages => "Repository packages",
+            Phase::AurPackages => "AUR packages",
+            Phase::Services => "Services",
+            Phase::UserCommands => "User commands",
+            Phase::FileWrites => "Config files",
+            Phase::SystemCommands => "Follow-up commands",
+        }
+    }
+}
+
+/// Progress of an installation, saved after every phase so an interrupted
+/// run can be resumed without going through the wizard again.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub answers: AnswerFile,
+    pub plan: InstallPlan,
+    /// Phases that finished without failures
+    pub completed: Vec<Phase>,
+    /// The rollback record so far
+    pub run: Option<RunRecord>,
+}
+
+fn path() -> PathBuf {
+    Path::new(STATE_DIR).join(CHECKPOINT_FILE)
+}
+
+/// The checkpoint of an installation that did not finish, if any.
+pub fn load() -> Option<Checkpoint> {
+    let path = path();
+    let content = fs::read_to_string(&path).ok()?;
+
+    let checkpoint: Checkpoint = match serde_json::from_str(&content) {
+        Ok(checkpoint) => checkpoint,
+        Err(e) => {
+            eprintln!("⚠️  Ignoring {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    if let Err(e) = checkpoint.answers.validate() {
+        eprintln!("⚠️  Ignoring {}: {}", path.display(), e);
+        return None;
+    }
+    Some(checkpoint)
+}
+
+/// Forgets the checkpoint, once the installation it tracks is done or dropped.
+pub fn clear() {
+    let _ = fs::remove_file(path());
+}
+
+impl Checkpoint {
+    /// Starts tracking a new installation and saves the first checkpoint.
+    pub fn new(config: &InstallConfig, plan: &InstallPlan) -> Self {
+        let checkpoint = Checkpoint {
+            answers: AnswerFile::from_config(config),
+            plan: plan.clone(),
+            ..Default::default()
+        };
+        checkpoint.save();
+        checkpoint
+    }
+
+    pub fn is_done(&self, phase: Phase) -> bool {
+        self.completed.contains(&phase)
+    }
+
+    /// Marks `phase` as done and saves the progress.
+    pub fn complete(&mut self, phase: Phase) {
+        if !self.is_done(phase) {
+            self.completed.push(phase);
+        }
+        self.update();
+    }
+
+    /// Saves the rollback record as it is now. Called once recording starts
+    /// and after every phase, failed or not, so a resumed run still knows
+    /// the snapshot taken before the install and every file it saved.
+    pub fn update(&mut self) {
+        self.run = rollback::snapshot();
+        self.save();
+    }
+
+    /// Writes the checkpoint. Dry runs leave nothing to resume, and a
+    /// checkpoint that cannot be saved must not stop the installation.
+    fn save(&self) {
+        if options().dry_run {
+            return;
+        }
+
+        let result = fs::create_dir_all(STATE_DIR)
+            .map_err(|e| e.to_string())
+            .and_then(|_| serde_json::to_string_pretty(self).map_err(|e| e.to_string()))
+            .and_then(|json| fs::write(path(), json + "\n").map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            eprintln!("⚠️  Could not save the installation progress: {}", e);
+        }
+    }
+
+    pub fn print(&self) {
+        let done: Vec<&str> = self.completed.iter().map(|p| p.title()).collect();
+        let left: Vec<&str> = Phase::ALL.iter().filter(|p| !self.is_done(**p)).map(|p| p.title()).collect();
+        println!(" Completed: {}", if done.is_empty() { "nothing yet".to_string() } else { done.join(", ") });
+        println!(" Left to do: {}", left.join(", "));
+    }
+}
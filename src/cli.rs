@@ -0,0 +1,94 @@
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+/// Interactive Hyprland installer for Arch Linux.
+#[derive(Debug, Parser)]
+#[command(name = "hyprland-installer", version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Commands>,
+
+    /// Show what would happen without changing the system
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+
+    /// Assume the default answer for every confirmation prompt
+    #[arg(long, short = 'y', global = true)]
+    pub yes: bool,
+
+    /// User whose home directory and AUR builds are used (defaults to $SUDO_USER)
+    #[arg(long, global = true, value_name = "NAME")]
+    pub user: Option<String>,
+
+    /// Log every command and action to this file (default: /var/log/hyprland-installer.log)
+    #[arg(long, global = true, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+
+    /// Answer file (TOML or JSON) that fills in wizard steps without prompting
+    #[arg(long, global = true, value_name = "PATH")]
+    pub answers: Option<PathBuf>,
+
+    /// Read hardware information from this directory instead of /sys (for testing)
+    #[arg(long, global = true, value_name = "PATH", default_value = "/sys", hide = true)]
+    pub sysfs_root: PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Subcommand)]
+pub enum Commands {
+    /// Run the wizard and install the selected components (default)
+    Install,
+    /// Run the wizard and print what would be installed, without asking to proceed
+    Plan {
+        /// Also write the plan as JSON to this file
+        #[arg(long, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
+    /// Only regenerate the auto-generated block in hyprland.conf
+    ApplyConfig,
+    /// Check the system for common problems
+    Doctor,
+    /// Remove the packages, services and config the installer added
+    Uninstall,
+    /// Undo the packages, services and files changed by an installation
+    Rollback {
+        /// Run to undo (see /var/lib/hyprland-installer/runs); defaults to the latest
+        #[arg(long, value_name = "ID")]
+        run: Option<u64>,
+    },
+}
+
+/// Global flags that apply to the whole run.
+#[derive(Debug)]
+pub struct Options {
+    pub dry_run: bool,
+    pub yes: bool,
+    pub user: Option<String>,
+    pub log_file: Option<PathBuf>,
+    /// Where hardware is detected from, normally `/sys`
+    pub sysfs_root: PathBuf,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            dry_run: false,
+            yes: false,
+            user: None,
+            log_file: None,
+            sysfs_root: PathBuf::from("/sys"),
+        }
+    }
+}
+
+static OPTIONS: OnceLock<Options> = OnceLock::new();
+
+/// Sets the global options for this run. Only the first call has any effect.
+pub fn set_options(options: Options) {
+    let _ = OPTIONS.set(options);
+}
+
+/// Returns the global options, or the defaults if they were never set.
+pub fn options() -> &'static Options {
+    OPTIONS.get_or_init(Options::default)
+}
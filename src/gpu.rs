@@ -0,0 +1,247 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// PCI vendor ids of the GPU makers the installer has drivers for.
+const VENDOR_NVIDIA: u16 = 0x10de;
+const VENDOR_AMD: u16 = 0x1002;
+const VENDOR_INTEL: u16 = 0x8086;
+
+/// PCI base class of display controllers (VGA, 3D, ...).
+const CLASS_DISPLAY: u32 = 0x03;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GpuVendor {
+    Nvidia,
+    Amd,
+    Intel,
+    Other,
+}
+
+impl GpuVendor {
+    fn from_id(id: u16) -> Self {
+        match id {
+            VENDOR_NVIDIA => GpuVendor::Nvidia,
+            VENDOR_AMD => GpuVendor::Amd,
+            VENDOR_INTEL => GpuVendor::Intel,
+            _ => GpuVendor::Other,
+        }
+    }
+
+    /// The `gpu_driver` catalog component for this vendor.
+    pub fn driver(self) -> &'static str {
+        match self {
+            GpuVendor::Nvidia => "nvidia",
+            GpuVendor::Amd => "amd",
+            GpuVendor::Intel => "intel",
+            GpuVendor::Other => "mesa",
+        }
+    }
+}
+
+impl fmt::Display for GpuVendor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            GpuVendor::Nvidia => "NVIDIA",
+            GpuVendor::Amd => "AMD",
+            GpuVendor::Intel => "Intel",
+            GpuVendor::Other => "Other",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A display controller found on the PCI bus.
+#[derive(Debug, Clone)]
+pub struct Gpu {
+    /// PCI address, e.g. `0000:01:00.0`
+    pub address: String,
+    pub vendor: GpuVendor,
+    pub vendor_id: u16,
+    pub device_id: Option<u16>,
+}
+
+impl fmt::Display for Gpu {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} GPU at {} ({:04x}", self.vendor, self.address, self.vendor_id)?;
+        if let Some(device_id) = self.device_id {
+            write!(f, ":{:04x}", device_id)?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl Gpu {
+    /// The stable DRM device name, which survives card0/card1 reordering.
+    pub fn by_path(&self) -> PathBuf {
+        PathBuf::from(format!("/dev/dri/by-path/pci-{}-card", self.address))
+    }
+}
+
+/// The (integrated, NVIDIA) pair on hybrid graphics laptops, if `gpus` has
+/// an NVIDIA card next to an Intel or AMD one.
+pub fn hybrid_pair(gpus: &[Gpu]) -> Option<(Gpu, Gpu)> {
+    let nvidia = gpus.iter().find(|gpu| gpu.vendor == GpuVendor::Nvidia)?;
+    let integrated = gpus
+        .iter()
+        .find(|gpu| matches!(gpu.vendor, GpuVendor::Intel | GpuVendor::Amd))?;
+    Some((integrated.clone(), nvidia.clone()))
+}
+
+/// Finds the GPUs under `sysfs_root` (normally `/sys`) by reading the
+/// `class` and `vendor` files of every PCI device. Taking the root as a
+/// parameter lets detection run against a fixture directory.
+pub fn detect_gpus(sysfs_root: &Path) -> Vec<Gpu> {
+    let entries = match fs::read_dir(sysfs_root.join("bus/pci/devices")) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut gpus: Vec<Gpu> = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|entry| {
+            let device = entry.path();
+            let class = read_hex(&device.join("class"))?;
+            if class >> 16 != CLASS_DISPLAY {
+                return None;
+            }
+
+            let vendor_id = read_hex(&device.join("vendor"))? as u16;
+            Some(Gpu {
+                address: entry.file_name().to_string_lossy().into_owned(),
+                vendor: GpuVendor::from_id(vendor_id),
+                vendor_id,
+                device_id: read_hex(&device.join("device")).map(|id| id as u16),
+            })
+        })
+        .collect();
+
+    gpus.sort_by(|a, b| a.address.cmp(&b.address));
+    gpus
+}
+
+/// Reads a sysfs attribute such as `0x030000`.
+fn read_hex(path: &Path) -> Option<u32> {
+    let content = fs::read_to_string(path).ok()?;
+    u32::from_str_radix(content.trim().trim_start_matches("0x"), 16).ok()
+}
+
+/// The driver to preselect for `gpus`. A discrete NVIDIA card wins over
+/// integrated graphics, since it needs the proprietary driver to work well.
+pub fn suggested_driver(gpus: &[Gpu]) -> Option<&'static str> {
+    [GpuVendor::Nvidia, GpuVendor::Amd, GpuVendor::Intel, GpuVendor::Other]
+        .into_iter()
+        .find(|vendor| gpus.iter().any(|gpu| gpu.vendor == *vendor))
+        .map(GpuVendor::driver)
+}
+
+/// Explains why `driver` does not fit the detected hardware, if it doesn't.
+pub fn driver_mismatch(gpus: &[Gpu], driver: &str) -> Option<String> {
+    if gpus.is_empty() {
+        return None;
+    }
+
+    let has = |vendor: GpuVendor| gpus.iter().any(|gpu| gpu.vendor == vendor);
+    match driver {
+        "nvidia" if !has(GpuVendor::Nvidia) => Some("the NVIDIA driver was chosen but no NVIDIA GPU was found".to_string()),
+        "amd" if !has(GpuVendor::Amd) => Some("the AMD driver was chosen but no AMD GPU was found".to_string()),
+        "intel" if !has(GpuVendor::Intel) => Some("the Intel driver was chosen but no Intel GPU was found".to_string()),
+        "amd" | "intel" | "mesa" if has(GpuVendor::Nvidia) => {
+            Some("an NVIDIA GPU was found but the NVIDIA driver was not chosen; nouveau works poorly with Hyprland".to_string())
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A throwaway sysfs tree with one PCI device per `(address, class, vendor, device)`.
+    struct Sysfs(PathBuf);
+
+    impl Sysfs {
+        fn new(name: &str, devices: &[(&str, &str, &str, &str)]) -> Self {
+            let root = std::env::temp_dir().join(format!("hyprland-installer-gpu-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&root);
+            for (address, class, vendor, device) in devices {
+                let dir = root.join("bus/pci/devices").join(address);
+                fs::create_dir_all(&dir).unwrap();
+                fs::write(dir.join("class"), format!("{}\n", class)).unwrap();
+                fs::write(dir.join("vendor"), format!("{}\n", vendor)).unwrap();
+                fs::write(dir.join("device"), format!("{}\n", device)).unwrap();
+            }
+            Sysfs(root)
+        }
+    }
+
+    impl Drop for Sysfs {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    const INTEL_IGPU: (&str, &str, &str, &str) = ("0000:00:02.0", "0x030000", "0x8086", "0x9a49");
+    const NVIDIA_DGPU: (&str, &str, &str, &str) = ("0000:01:00.0", "0x030200", "0x10de", "0x2520");
+    const AMD_GPU: (&str, &str, &str, &str) = ("0000:03:00.0", "0x030000", "0x1002", "0x73bf");
+    /// An Intel audio controller: right vendor, wrong class
+    const INTEL_AUDIO: (&str, &str, &str, &str) = ("0000:00:1f.3", "0x040380", "0x8086", "0xa0c8");
+
+    #[test]
+    fn detect_gpus_reads_display_controllers_only() {
+        let sysfs = Sysfs::new("detect", &[NVIDIA_DGPU, INTEL_AUDIO, INTEL_IGPU]);
+        let gpus = detect_gpus(&sysfs.0);
+
+        assert_eq!(gpus.len(), 2);
+        assert_eq!(gpus[0].address, "0000:00:02.0");
+        assert_eq!(gpus[0].vendor, GpuVendor::Intel);
+        assert_eq!(gpus[1].vendor, GpuVendor::Nvidia);
+        assert_eq!(gpus[1].vendor_id, 0x10de);
+        assert_eq!(gpus[1].device_id, Some(0x2520));
+    }
+
+    #[test]
+    fn detect_gpus_without_pci_bus_finds_nothing() {
+        let sysfs = Sysfs::new("empty", &[]);
+        assert!(detect_gpus(&sysfs.0).is_empty());
+    }
+
+    #[test]
+    fn suggested_driver_prefers_nvidia() {
+        let hybrid = detect_gpus(&Sysfs::new("suggest-hybrid", &[INTEL_IGPU, NVIDIA_DGPU]).0);
+        assert_eq!(suggested_driver(&hybrid), Some("nvidia"));
+
+        let amd = detect_gpus(&Sysfs::new("suggest-amd", &[AMD_GPU]).0);
+        assert_eq!(suggested_driver(&amd), Some("amd"));
+
+        assert_eq!(suggested_driver(&[]), None);
+    }
+
+    #[test]
+    fn driver_mismatch_flags_missing_and_ignored_hardware() {
+        let intel = detect_gpus(&Sysfs::new("mismatch-intel", &[INTEL_IGPU]).0);
+        assert!(driver_mismatch(&intel, "nvidia").is_some());
+        assert_eq!(driver_mismatch(&intel, "intel"), None);
+        assert_eq!(driver_mismatch(&intel, "mesa"), None);
+
+        let hybrid = detect_gpus(&Sysfs::new("mismatch-hybrid", &[INTEL_IGPU, NVIDIA_DGPU]).0);
+        assert!(driver_mismatch(&hybrid, "intel").unwrap().contains("NVIDIA GPU was found"));
+        assert_eq!(driver_mismatch(&hybrid, "nvidia"), None);
+
+        assert_eq!(driver_mismatch(&[], "nvidia"), None);
+    }
+
+    #[test]
+    fn hybrid_pair_needs_nvidia_next_to_an_integrated_gpu() {
+        let hybrid = detect_gpus(&Sysfs::new("hybrid", &[NVIDIA_DGPU, INTEL_IGPU]).0);
+        let (integrated, nvidia) = hybrid_pair(&hybrid).unwrap();
+        assert_eq!(integrated.vendor, GpuVendor::Intel);
+        assert_eq!(nvidia.vendor, GpuVendor::Nvidia);
+
+        let nvidia_only = detect_gpus(&Sysfs::new("hybrid-nvidia", &[NVIDIA_DGPU]).0);
+        assert!(hybrid_pair(&nvidia_only).is_none());
+
+        let amd_only = detect_gpus(&Sysfs::new("hybrid-amd", &[AMD_GPU]).0);
+        assert!(hybrid_pair(&amd_only).is_none());
+    }
+}
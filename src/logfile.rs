@@ -0,0 +1,104 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::cli::options;
+
+/// Log written by real runs when no `--log-file` is given.
+const DEFAULT_LOG_FILE: &str = "/var/log/hyprland-installer.log";
+
+/// The log file of this run: the `--log-file`, or the default one unless
+/// this is a dry run (which must not leave anything behind).
+pub fn path() -> Option<&'static Path> {
+    match options().log_file {
+        Some(ref path) => Some(path),
+        None if options().dry_run => None,
+        None => Some(Path::new(DEFAULT_LOG_FILE)),
+    }
+}
+
+fn timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Set once something has been written to the log file.
+static WRITTEN: AtomicBool = AtomicBool::new(false);
+
+/// Set once a failure to write the log file has been reported.
+static WARNED: AtomicBool = AtomicBool::new(false);
+
+/// Appends `text` to the log file. Logging must never break an install,
+/// so a log file that cannot be written is only reported, once.
+fn append(text: &str) {
+    let path = match path() {
+        Some(path) => path,
+        None => return,
+    };
+
+    let result = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(text.as_bytes()));
+    match result {
+        Ok(()) => WRITTEN.store(true, Ordering::Relaxed),
+        Err(e) if !WARNED.swap(true, Ordering::Relaxed) => {
+            eprintln!("⚠️  Could not write the log file {}: {}; commands will not be logged", path.display(), e);
+        }
+        Err(_) => {}
+    }
+}
+
+/// The log file, once something has been written to it.
+pub fn written() -> Option<&'static Path> {
+    path().filter(|_| WRITTEN.load(Ordering::Relaxed))
+}
+
+/// Appends a timestamped line to the log file.
+pub fn record(message: &str) {
+    append(&format!("[{}] {}\n", timestamp(), message));
+}
+
+/// One executed command, as it goes into the log.
+pub struct CommandEntry<'a> {
+    pub command: String,
+    pub cwd: &'a Path,
+    pub user: &'a str,
+    /// Exit code, signal or spawn error
+    pub result: String,
+    pub duration: Duration,
+    pub stdout: &'a [u8],
+    pub stderr: &'a [u8],
+}
+
+/// Appends an executed command with its context and captured output.
+pub fn record_command(entry: &CommandEntry) {
+    let mut text = format!(
+        "[{}] $ {}\n    cwd: {}  user: {}  {}  duration: {:.2}s\n",
+        timestamp(),
+        entry.command,
+        entry.cwd.display(),
+        entry.user,
+        entry.result,
+        entry.duration.as_secs_f64()
+    );
+
+    for (name, output) in [("stdout", entry.stdout), ("stderr", entry.stderr)] {
+        if output.is_empty() {
+            continue;
+        }
+        text.push_str(&format!("    --- {} ---\n", name));
+        for line in String::from_utf8_lossy(output).lines() {
+            text.push_str("    ");
+            text.push_str(line);
+            text.push('\n');
+        }
+    }
+
+    append(&text);
+}
@@ -1,15 +1,41 @@
+mod answers;
+mod bootloader;
+mod catalog;
+mod checkpoint;
+mod cli;
+mod gpu;
+mod logfile;
+mod mkinitcpio;
+mod nvidia;
+mod pacman;
+mod plan;
+mod report;
+mod rollback;
+mod runner;
+mod state;
+mod uninstall;
+
+use answers::AnswerFile;
+use catalog::{catalog, Component, Step, StepKind};
+use checkpoint::{Checkpoint, Phase};
+use clap::Parser;
+use cli::{options, set_options, Cli, Commands, Options};
+use pacman::PackageDb;
+use plan::{FileWrite, InstallPlan, Replacement, SystemCommand, UserCommand};
+use report::{Outcome, Report};
+use runner::{run_checked, runner, Cmd, CommandRunner};
 use inquire::{Select, MultiSelect, Confirm, Text};
 use std::process::Command;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::os::unix::process::CommandExt;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 struct InstallConfig {
-    dry_run: bool,
     aur_helper: Option<String>,
     greeter: Option<String>,
     gpu_driver: Option<String>,
+    hybrid_graphics: bool,
     hyprland_version: Option<String>,
     uwsm: bool,
     xdg_user_dirs: bool,
@@ -23,467 +49,865 @@ struct InstallConfig {
     status_bar: Option<String>,
     wallpaper_utils: Vec<String>,
     app_launcher: Option<String>,
+    prime_run_keybinding: bool,
     color_picker: Option<String>,
     clipboard_manager: Option<String>,
     gui_file_manager: Option<String>,
     tui_file_manager: Option<String>,
 }
 
-impl Default for InstallConfig {
-    fn default() -> Self {
-        Self {
-            dry_run: false,
-            aur_helper: None,
-            greeter: None,
-            gpu_driver: None,
-            hyprland_version: None,
-            uwsm: false,
-            xdg_user_dirs: false,
-            terminal: None,
-            shell: None,
-            notification_daemon: None,
-            audio: None,
-            xdg_portal: false,
-            auth_agent: None,
-            qt_support: false,
-            status_bar: None,
-            wallpaper_utils: Vec::new(),
-            app_launcher: None,
-            color_picker: None,
-            clipboard_manager: None,
-            gui_file_manager: None,
-            tui_file_manager: None,
+impl InstallConfig {
+    /// The component ids chosen for a catalog step.
+    fn selection(&self, key: &str) -> Vec<String> {
+        let single = |value: &Option<String>| value.iter().cloned().collect();
+        let toggle = |on: bool| -> Vec<String> {
+            if on {
+                catalog().components_for(key).take(1).map(|c| c.id.clone()).collect()
+            } else {
+                Vec::new()
+            }
+        };
+        
+        match key {
+            "aur_helper" => single(&self.aur_helper),
+            "greeter" => single(&self.greeter),
+            "gpu_driver" => single(&self.gpu_driver),
+            "hybrid_graphics" => toggle(self.hybrid_graphics),
+            "prime_run_keybinding" => toggle(self.prime_run_keybinding),
+            "hyprland_version" => single(&self.hyprland_version),
+            "xdg_user_dirs" => toggle(self.xdg_user_dirs),
+            "uwsm" => toggle(self.uwsm),
+            "terminal" => single(&self.terminal),
+            "shell" => single(&self.shell),
+            "notification_daemon" => single(&self.notification_daemon),
+            "audio" => single(&self.audio),
+            "xdg_portal" => toggle(self.xdg_portal),
+            "auth_agent" => single(&self.auth_agent),
+            "qt_support" => toggle(self.qt_support),
+            "status_bar" => single(&self.status_bar),
+            "wallpaper_utils" => self.wallpaper_utils.clone(),
+            "app_launcher" => single(&self.app_launcher),
+            "color_picker" => single(&self.color_picker),
+            "clipboard_manager" => single(&self.clipboard_manager),
+            "gui_file_manager" => single(&self.gui_file_manager),
+            "tui_file_manager" => single(&self.tui_file_manager),
+            _ => Vec::new(),
+        }
+    }
+    
+    /// Stores the component ids chosen for a catalog step. An empty list
+    /// skips the step (or answers "no" for a toggle).
+    fn set_selection(&mut self, key: &str, ids: Vec<String>) {
+        let first = ids.first().cloned();
+        let on = !ids.is_empty();
+        
+        match key {
+            "aur_helper" => self.aur_helper = first,
+            "greeter" => self.greeter = first,
+            "gpu_driver" => self.gpu_driver = first,
+            "hybrid_graphics" => self.hybrid_graphics = on,
+            "prime_run_keybinding" => self.prime_run_keybinding = on,
+            "hyprland_version" => self.hyprland_version = first,
+            "xdg_user_dirs" => self.xdg_user_dirs = on,
+            "uwsm" => self.uwsm = on,
+            "terminal" => self.terminal = first,
+            "shell" => self.shell = first,
+            "notification_daemon" => self.notification_daemon = first,
+            "audio" => self.audio = first,
+            "xdg_portal" => self.xdg_portal = on,
+            "auth_agent" => self.auth_agent = first,
+            "qt_support" => self.qt_support = on,
+            "status_bar" => self.status_bar = first,
+            "wallpaper_utils" => self.wallpaper_utils = ids,
+            "app_launcher" => self.app_launcher = first,
+            "color_picker" => self.color_picker = first,
+            "clipboard_manager" => self.clipboard_manager = first,
+            "gui_file_manager" => self.gui_file_manager = first,
+            "tui_file_manager" => self.tui_file_manager = first,
+            _ => {}
         }
     }
 }
 
+/// systemd alias pointing at the enabled display manager's unit
+const DISPLAY_MANAGER_LINK: &str = "/etc/systemd/system/display-manager.service";
+
+// Markers around the section of hyprland.conf that the installer owns
+const MARKER_START: &str = "# === AUTO-GENERATED EXEC-ONCE START ===";
+const MARKER_END: &str = "# === AUTO-GENERATED EXEC-ONCE END ===";
+
 fn main() {
+    let cli = Cli::parse();
+    
     println!("\n╔═════════════════════════════════════════════╗");
     println!("║        Arch Linux Hyprland Installer        ║");
     println!("╚═════════════════════════════════════════════╝\n");
     
-    let mut config = InstallConfig::default();
+    let command = cli.command.clone().unwrap_or(Commands::Install);
     
-    // Ask about dry run mode
-    match Confirm::new("Run in DRY RUN mode? (No actual installation or file changes)")
-        .with_default(false)
-        .prompt()
-    {
-        Ok(true) => {
-            config.dry_run = true;
-            println!("\n DRY RUN MODE ENABLED - No changes will be made to your system\n");
-        }
-        Ok(false) => {
-            if !check_root() {
-                println!("⚠️  This installer should be run as root or with sudo privileges.");
-                println!("Some package installations may fail without proper permissions.\n");
-            }
-        }
-        Err(_) => {
-            if !check_root() {
-                println!("⚠️  This installer should be run as root or with sudo privileges.");
-                println!("Some package installations may fail without proper permissions.\n");
-            }
+    // Settle dry run mode before anything touches the system
+    let dry_run = match command {
+        Commands::Plan { .. } => true,
+        Commands::Install if !cli.dry_run && !cli.yes => {
+            Confirm::new("Run in DRY RUN mode? (No actual installation or file changes)")
+                .with_default(false)
+                .prompt()
+                .unwrap_or(false)
         }
+        _ => cli.dry_run,
+    };
+    
+    set_options(Options {
+        dry_run,
+        yes: cli.yes,
+        user: cli.user.clone(),
+        log_file: cli.log_file.clone(),
+        sysfs_root: cli.sysfs_root.clone(),
+    });
+    logfile::record(&format!("Starting {:?}{}", command, if dry_run { " (dry run)" } else { "" }));
+    
+    if dry_run {
+        println!("\n DRY RUN MODE ENABLED - No changes will be made to your system\n");
+    } else if command != Commands::Doctor && !check_root() {
+        println!("⚠️  This installer should be run as root or with sudo privileges.");
+        println!("Some package installations may fail without proper permissions.\n");
+    }
+    
+    match command {
+        Commands::Install => run_install(&load_answers(&cli)),
+        Commands::Plan { ref output } => run_plan(&load_answers(&cli), output.as_deref()),
+        Commands::ApplyConfig => run_apply_config(&load_answers(&cli)),
+        Commands::Doctor => run_doctor(),
+        Commands::Uninstall => run_uninstall(),
+        Commands::Rollback { run } => run_rollback(run),
     }
+}
 
-    // Step through each configuration option
-    select_aur_helper(&mut config);
-    select_greeter(&mut config);
-    select_gpu_driver(&mut config);
-    select_hyprland_version(&mut config);
-    select_xdg_user_dirs(&mut config);
-    select_uwsm(&mut config);
-    select_terminal_shell(&mut config);
-    select_notification_daemon(&mut config);
-    select_audio(&mut config);
-    select_xdg_portal(&mut config);
-    select_auth_agent(&mut config);
-    select_qt_support(&mut config);
-    select_status_bar(&mut config);
-    select_wallpaper_utils(&mut config);
-    select_app_launcher(&mut config);
-    select_color_picker(&mut config);
-    select_clipboard_manager(&mut config);
-    select_file_manager(&mut config);
-
-    // Summary and confirmation
-    display_summary(&config);
+/// Loads the `--answers` file, or an empty one when none was given.
+/// An invalid answer file aborts the run before any prompt is shown.
+fn load_answers(cli: &Cli) -> AnswerFile {
+    let path = match cli.answers {
+        Some(ref path) => path,
+        None => return AnswerFile::default(),
+    };
     
-    if confirm_installation(&config) {
-        perform_installation(&config);
-        
-        // Update hyprland.conf with exec-once statements
-        if config.dry_run {
-            println!("\n🧪 DRY RUN: Showing what would be added to hyprland.conf...");
-            show_config_preview(&config);
-        } else if Confirm::new("Would you like to update your hyprland.conf with exec-once statements?")
-            .with_default(true)
-            .prompt()
-            .unwrap_or(false)
-        {
-            update_hyprland_config(&config);
+    match AnswerFile::load(path) {
+        Ok(answers) => {
+            println!("✓ Loaded answers from: {}\n", path.display());
+            answers
         }
-        
-        // Prompt to start Hyprland
-        if !config.dry_run {
-            println!();
-            match Confirm::new("Would you like to start Hyprland now?")
-                .with_default(false)
-                .prompt()
-            {
-                Ok(true) => {
-                    println!("\n🚀 Starting Hyprland...\n");
-                    let _ = Command::new("Hyprland").exec();
-                    // exec() replaces the current process, so this line won't be reached
-                }
-                Ok(false) => {
-                    println!("\n✓ Installation complete. Start Hyprland later by running: Hyprland");
-                }
-                Err(_) => {
-                    println!("\n✓ Installation complete. Start Hyprland later by running: Hyprland");
-                }
-            }
+        Err(e) => {
+            eprintln!("❌ Invalid answer file: {}", e);
+            std::process::exit(1);
         }
+    }
+}
+
+/// Asks a yes/no question, or takes `default` without asking under `--yes`.
+fn confirm(message: &str, default: bool) -> bool {
+    if options().yes {
+        println!("{} {} (--yes)", message, if default { "Yes" } else { "No" });
+        return default;
+    }
+    
+    Confirm::new(message)
+        .with_default(default)
+        .prompt()
+        .unwrap_or(false)
+}
+
+/// Offers to start from the setup recorded by the last install.
+fn previous_setup() -> Option<state::State> {
+    let previous = state::load()?;
+    
+    println!("ℹ️  Found the setup of a previous install in {}", state::path().display());
+    if confirm("Modify the existing setup? (No starts from scratch)", true) {
+        Some(previous)
     } else {
-        println!("\n❌ Installation cancelled.");
+        None
     }
 }
 
-fn check_root() -> bool {
-    let output = Command::new("id")
-        .arg("-u")
-        .output()
-        .expect("Failed to check user ID");
+/// Offers to pick up an installation that was interrupted. Declining
+/// drops its progress.
+fn unfinished_installation() -> Option<Checkpoint> {
+    let checkpoint = checkpoint::load()?;
     
-    let uid = String::from_utf8_lossy(&output.stdout).trim().to_string();
-    uid == "0"
+    println!("⚠️  An earlier installation did not finish.");
+    checkpoint.print();
+    if confirm("\nResume it without going through the questions again?", true) {
+        return Some(checkpoint);
+    }
+    if !options().dry_run {
+        checkpoint::clear();
+    }
+    None
+}
+
+fn resume_installation(checkpoint: Checkpoint) {
+    let config = checkpoint.answers.config();
+    let plan = checkpoint.plan.clone();
+    display_plan(&plan);
+    
+    if confirm_installation() {
+        let report = perform_installation(&config, &plan, checkpoint);
+        finish_installation(&config, &report);
+    } else {
+        println!("\n❌ Installation cancelled.");
+    }
 }
 
-fn select_aur_helper(config: &mut InstallConfig) {
-    println!("\n═══ Step 1: AUR Helper ═══");
+fn run_install(answers: &AnswerFile) {
+    if let Some(checkpoint) = unfinished_installation() {
+        resume_installation(checkpoint);
+        return;
+    }
     
-    // Check if yay or paru is already installed
-    let yay_installed = Command::new("which").arg("yay").output().map(|o| o.status.success()).unwrap_or(false);
-    let paru_installed = Command::new("which").arg("paru").output().map(|o| o.status.success()).unwrap_or(false);
+    let previous = previous_setup();
+    let mut config = run_wizard(answers, previous.as_ref());
     
-    if yay_installed || paru_installed {
-        let installed = if yay_installed { "yay" } else { "paru" };
-        println!("✓ AUR helper already installed: {}", installed);
+    // Update hyprland.conf with exec-once statements
+    let update_config = options().dry_run
+        || confirm("Would you like to update your hyprland.conf with exec-once statements?", true);
+    
+    // Summary and plan, refreshed after every change made from the summary
+    let mut plan = loop {
+        display_summary(&config);
+        let plan = InstallPlan::from_config(&config, update_config);
+        display_plan(&plan);
+        if let Some(ref previous) = previous {
+            previous.print_changes(&config, &plan);
+        }
         
-        match Confirm::new(&format!("Keep using {}?", installed))
-            .with_default(true)
-            .prompt()
-        {
-            Ok(true) => {
-                config.aur_helper = Some(installed.to_string());
-                return;
-            }
-            _ => {}
+        if !edit_from_summary(&mut config) {
+            break plan;
+        }
+    };
+    offer_save_answers(&config);
+    
+    if !plan.disable_services.is_empty() {
+        let message = format!(
+            "{} is the active display manager. Disable it so the chosen greeter can take over?",
+            plan.disable_services.join(", ")
+        );
+        if !confirm(&message, true) {
+            plan.keep_display_manager(&config);
+            println!("ℹ️  Keeping the current display manager; the chosen greeter will be installed but not enabled");
         }
     }
     
-    let options = vec!["yay (recommended)", "paru", "SKIP (install manually later)"];
+    if !plan.replacements.is_empty() && !confirm("Remove the conflicting installed packages listed above?", true) {
+        plan.drop_replacements();
+        println!("ℹ️  Left out the packages that would replace installed ones");
+    }
     
-    match Select::new("Select an AUR helper to install:", options).prompt() {
-        Ok(choice) => {
-            config.aur_helper = match choice {
-                "SKIP (install manually later)" => None,
-                option => Some(option.split_whitespace().next().unwrap().to_string()),
-            };
-        }
-        Err(_) => config.aur_helper = None,
+    if confirm_installation() {
+        let checkpoint = Checkpoint::new(&config, &plan);
+        let report = perform_installation(&config, &plan, checkpoint);
+        finish_installation(&config, &report);
+    } else {
+        println!("\n❌ Installation cancelled.");
     }
 }
 
-fn select_greeter(config: &mut InstallConfig) {
-    println!("\n═══ Step 2: Display Manager ═══");
-    
-    let options = vec!["sddm (default)", "gdm", "lightdm", "greetd", "SKIP"];
-    
-    match Select::new("Select a display manager:", options).prompt() {
-        Ok(choice) => {
-            config.greeter = match choice {
-                "SKIP" => None,
-                option => Some(option.split_whitespace().next().unwrap().to_string()),
-            };
+/// Wraps up after `perform_installation`: exits non-zero after failures,
+/// otherwise offers to start Hyprland.
+fn finish_installation(config: &InstallConfig, report: &Report) {
+    if options().dry_run {
+        println!("\n🧪 DRY RUN: Showing what would be added to hyprland.conf...");
+        show_config_preview(config);
+    }
+    
+    // A partial install exits non-zero, so provisioning scripts notice
+    if report.has_failures() {
+        std::process::exit(1);
+    }
+    
+    // Prompt to start Hyprland
+    if !options().dry_run {
+        println!();
+        if confirm("Would you like to start Hyprland now?", false) {
+            println!("\n🚀 Starting Hyprland...\n");
+            let _ = Command::new("Hyprland").exec();
+            // exec() replaces the current process, so this line won't be reached
+        } else {
+            println!("\n✓ Installation complete. Start Hyprland later by running: Hyprland");
         }
-        Err(_) => config.greeter = None,
     }
 }
 
-fn select_gpu_driver(config: &mut InstallConfig) {
-    println!("\n═══ Step 3: GPU Driver Selection ═══");
+fn run_plan(answers: &AnswerFile, output: Option<&Path>) {
+    let previous = previous_setup();
+    let config = run_wizard(answers, previous.as_ref());
     
-    let options = vec!["nvidia", "amd", "intel", "open-source (mesa)", "SKIP"];
+    display_summary(&config);
     
-    match Select::new("Select your GPU driver:", options).prompt() {
-        Ok(choice) => {
-            config.gpu_driver = match choice {
-                "SKIP" => None,
-                option => Some(option.to_string()),
-            };
+    let plan = InstallPlan::from_config(&config, true);
+    display_plan(&plan);
+    if let Some(ref previous) = previous {
+        previous.print_changes(&config, &plan);
+    }
+    show_config_preview(&config);
+    
+    if let Some(path) = output {
+        match plan.save_json(path) {
+            Ok(_) => println!("✓ Saved plan to: {}", path.display()),
+            Err(e) => eprintln!("⚠️  {}", e),
         }
-        Err(_) => config.gpu_driver = None,
     }
 }
 
-fn select_hyprland_version(config: &mut InstallConfig) {
-    println!("\n═══ Step 4: Hyprland Installation ═══");
+fn run_apply_config(answers: &AnswerFile) {
+    // The existing setup already answers every step, so it is used as it
+    // is and only changed from the summary
+    let mut config = match previous_setup() {
+        Some(previous) => {
+            let mut config = previous.config();
+            answers.apply(&mut config);
+            config
+        }
+        None => run_wizard(answers, None),
+    };
     
-    let options = vec!["hyprland (default)", "hyprland-git", "hyprland-meta", "SKIP"];
+    display_summary(&config);
+    while edit_from_summary(&mut config) {
+        display_summary(&config);
+    }
     
-    match Select::new("Select Hyprland package:", options).prompt() {
-        Ok(choice) => {
-            config.hyprland_version = match choice {
-                "SKIP" => None,
-                option => Some(option.split_whitespace().next().unwrap().to_string()),
-            };
+    let write = match plan::hyprland_config_write(&config) {
+        Some(write) => write,
+        None => {
+            println!("ℹ️  No exec-once statements to add");
+            return;
         }
-        Err(_) => config.hyprland_version = None,
+    };
+    
+    if options().dry_run {
+        show_config_preview(&config);
+    } else if confirm("Update hyprland.conf with these exec-once statements?", true) && write_file(&write).is_err() {
+        std::process::exit(1);
     }
 }
 
-fn select_xdg_user_dirs(config: &mut InstallConfig) {
-    println!("\n═══ Step 5: XDG User Directories ═══");
-    println!("Creates standard directories like Documents, Downloads, Pictures, etc.");
-    
-    match Confirm::new("Install xdg-user-dirs?")
-        .with_default(true)
-        .prompt()
-    {
-        Ok(answer) => config.xdg_user_dirs = answer,
-        Err(_) => config.xdg_user_dirs = false,
+/// What the user did at a wizard prompt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StepAction {
+    Next,
+    Back,
+}
+
+/// Whether a conditional step applies to the answers given so far.
+fn step_applies(step: &Step, config: &InstallConfig) -> bool {
+    match step.key.as_str() {
+        // Only for an NVIDIA card next to an integrated GPU
+        "hybrid_graphics" => hybrid_gpus(config).is_some(),
+        "prime_run_keybinding" => config.hybrid_graphics && config.app_launcher.is_some(),
+        _ => true,
     }
 }
 
-fn select_uwsm(config: &mut InstallConfig) {
-    println!("\n═══ Step 6: UWSM (Universal Wayland Session Manager) ═══");
-    
-    match Confirm::new("Install UWSM?")
-        .with_default(false)
-        .prompt()
-    {
-        Ok(answer) => config.uwsm = answer,
-        Err(_) => config.uwsm = false,
+/// Asks every step the answer file leaves open, as a state machine that
+/// can go back to any earlier step. A step that was already answered
+/// starts out on that answer; otherwise, with a `previous` setup, on the
+/// choice made last time.
+fn run_wizard(answers: &AnswerFile, previous: Option<&state::State>) -> InstallConfig {
+    let mut config = InstallConfig::default();
+    answers.apply(&mut config);
+    let previous = previous.map(state::State::config);
+    
+    let steps = &catalog().steps;
+    let mut index = 0;
+    // Indices of the steps shown so far, for going back
+    let mut history: Vec<usize> = Vec::new();
+    let mut answered: Vec<&str> = Vec::new();
+    let mut shown_header = None;
+    
+    while let Some(step) = steps.get(index) {
+        if answers.is_answered(&step.key) {
+            index += 1;
+            continue;
+        }
+        
+        // A step that no longer applies (e.g. after going back to change the
+        // GPU driver) must not keep its old answer
+        if !step_applies(step, &config) {
+            config.set_selection(&step.key, Vec::new());
+            index += 1;
+            continue;
+        }
+        
+        // Steps that share a number (terminal & shell, GUI & TUI file manager) share a header
+        if shown_header != Some(step.number) {
+            println!("\n═══ Step {}: {} ═══", step.number, step.title);
+            if let Some(ref description) = step.description {
+                println!("{}", description);
+            }
+            shown_header = Some(step.number);
+        }
+        
+        let preselect = if answered.contains(&step.key.as_str()) {
+            Some(config.selection(&step.key))
+        } else {
+            previous.as_ref().map(|previous| previous.selection(&step.key))
+        };
+        let preselect = preselect.as_deref();
+        let can_go_back = !history.is_empty();
+        
+        let action = match step.key.as_str() {
+            "aur_helper" => select_aur_helper(&mut config, step, preselect, can_go_back),
+            "gpu_driver" => select_gpu_driver(&mut config, step, preselect, can_go_back),
+            _ => select_step(&mut config, step, preselect, can_go_back),
+        };
+        
+        match action {
+            StepAction::Next => {
+                if !answered.contains(&step.key.as_str()) {
+                    answered.push(&step.key);
+                }
+                history.push(index);
+                index += 1;
+            }
+            StepAction::Back => {
+                index = history.pop().unwrap_or(0);
+                shown_header = None;
+            }
+        }
     }
+    
+    check_gpu_driver(&config);
+    
+    config
 }
 
-fn select_terminal_shell(config: &mut InstallConfig) {
-    println!("\n═══ Step 7: Terminal & Shell Selection ═══");
+fn run_doctor() {
+    println!("═══ System Check ═══\n");
     
-    // Terminal selection
-    let term_options = vec!["kitty (default)", "foot", "alacritty", "ghostty", "SKIP"];
+    let mut problems = 0;
+    let mut check = |ok: bool, good: String, bad: String| {
+        if ok {
+            println!("✓ {}", good);
+        } else {
+            println!("⚠️  {}", bad);
+            problems += 1;
+        }
+    };
     
-    match Select::new("Select a terminal emulator:", term_options).prompt() {
-        Ok(choice) => {
-            config.terminal = match choice {
-                "SKIP" => None,
-                option => Some(option.split_whitespace().next().unwrap().to_string()),
-            };
+    check(
+        check_root(),
+        "Running with root privileges".to_string(),
+        "Not running as root - package installation will fail".to_string(),
+    );
+    check(
+        command_exists("pacman"),
+        "pacman is available".to_string(),
+        "pacman not found - this installer only supports Arch Linux".to_string(),
+    );
+    
+    let aur_helper = ["yay", "paru"].into_iter().find(|helper| command_exists(helper));
+    check(
+        aur_helper.is_some(),
+        format!("AUR helper installed: {}", aur_helper.unwrap_or_default()),
+        "No AUR helper (yay/paru) installed - AUR packages cannot be installed".to_string(),
+    );
+    check(
+        command_exists("Hyprland"),
+        "Hyprland is installed".to_string(),
+        "Hyprland is not installed".to_string(),
+    );
+    
+    match get_hyprland_config_path() {
+        Some(path) => {
+            let content = fs::read_to_string(&path).unwrap_or_default();
+            check(
+                path.exists(),
+                format!("Found hyprland.conf at: {}", path.display()),
+                format!("No hyprland.conf at: {}", path.display()),
+            );
+            if content.contains(MARKER_START) {
+                println!("✓ hyprland.conf contains an auto-generated exec-once section");
+            }
+            let autostart_path = path.with_file_name("hyprland-autostart.conf");
+            if autostart_path.exists() {
+                println!("✓ Found autostart config at: {}", autostart_path.display());
+            }
         }
-        Err(_) => config.terminal = None,
+        None => check(false, String::new(), "Could not determine config path".to_string()),
     }
     
-    // Shell selection
-    let shell_options = vec!["bash (default)", "zsh", "fish", "SKIP"];
-    
-    match Select::new("Select a shell:", shell_options).prompt() {
-        Ok(choice) => {
-            config.shell = match choice {
-                "SKIP" => None,
-                option => Some(option.split_whitespace().next().unwrap().to_string()),
-            };
-        }
-        Err(_) => config.shell = None,
+    if problems == 0 {
+        println!("\n✓ No problems found");
+    } else {
+        println!("\n⚠️  {} problem(s) found", problems);
     }
 }
 
-fn select_notification_daemon(config: &mut InstallConfig) {
-    println!("\n═══ Step 8: Notification Daemon ═══");
+fn run_uninstall() {
+    println!("═══ Uninstall ═══\n");
     
-    let options = vec!["dunst", "mako", "fnott", "swaync", "SKIP"];
+    let mut removal = uninstall::Removal::plan();
     
-    match Select::new("Select a notification daemon:", options).prompt() {
-        Ok(choice) => {
-            config.notification_daemon = match choice {
-                "SKIP" => None,
-                option => Some(option.to_string()),
-            };
+    let config_path = get_hyprland_config_path();
+    if config_path.is_none() {
+        eprintln!("⚠️  Could not determine config path");
+    }
+    let autostart_path = config_path.as_ref().map(|path| path.with_file_name("hyprland-autostart.conf"));
+    let autostart_path = autostart_path.filter(|path| path.exists());
+    
+    let stripped = config_path
+        .as_ref()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|content| strip_generated_section(&content));
+    
+    if removal.is_empty() && stripped.is_none() && autostart_path.is_none() {
+        println!("ℹ️  Nothing to remove: no installer-managed packages, services or config found");
+        return;
+    }
+    
+    removal.print();
+    if let (Some(_), Some(ref path)) = (&stripped, &config_path) {
+        println!(" Would remove the auto-generated exec-once section from: {}", path.display());
+    }
+    if let Some(ref path) = autostart_path {
+        println!(" Would delete: {}", path.display());
+    }
+    
+    if options().dry_run {
+        println!("\n DRY RUN complete! No changes were made to your system.");
+        return;
+    }
+    
+    // Without records only the generated config is removed; the packages were just listed
+    if !removal.can_remove() && stripped.is_none() && autostart_path.is_none() {
+        return;
+    }
+    let question = if removal.can_remove() {
+        "\nRemove these installer-managed components?"
+    } else {
+        "\nRemove the auto-generated Hyprland config?"
+    };
+    
+    // Taken from the last plan, the list may hold packages the user had before
+    if !confirm(question, removal.source == uninstall::Source::Records) {
+        println!("\n❌ Uninstall cancelled.");
+        return;
+    }
+    
+    let mut report = Report::default();
+    
+    if removal.can_remove() {
+        for service in &removal.services {
+            report.add(format!("Disable {}", service), disable_service(service));
+        }
+        
+        if !removal.packages.is_empty() {
+            println!("\n Removing packages...");
+            report.add("Remove packages", remove_packages(&removal.packages));
         }
-        Err(_) => config.notification_daemon = None,
     }
-}
-
-fn select_audio(config: &mut InstallConfig) {
-    println!("\n═══ Step 9: Audio System ═══");
     
-    let options = vec!["pipewire (recommended)", "pulseaudio", "SKIP"];
+    // Undo the installer's system config (initramfs, modprobe, kernel
+    // parameters), then rebuild what is generated from it
+    for file in &removal.files {
+        report.add(format!("Restore {}", file.path.display()), rollback::restore_file(file, false));
+    }
+    let paths: Vec<&Path> = removal.files.iter().map(|f| f.path.as_path()).collect();
+    for command in bootloader::regeneration_commands(&paths) {
+        report.add(command.description.clone(), run_system_command(&command));
+    }
     
-    match Select::new("Select audio system:", options).prompt() {
-        Ok(choice) => {
-            config.audio = match choice {
-                "SKIP" => None,
-                "pipewire (recommended)" => Some("pipewire".to_string()),
-                option => Some(option.to_string()),
-            };
+    if let (Some(content), Some(ref config_path)) = (stripped, &config_path) {
+        report.add(format!("Strip {}", config_path.display()), strip_config(config_path, &content));
+    }
+    
+    if let Some(ref autostart_path) = autostart_path {
+        let result = match fs::remove_file(autostart_path) {
+            Ok(_) => {
+                println!("✓ Deleted: {}", autostart_path.display());
+                logfile::record(&format!("Deleted {}", autostart_path.display()));
+                Ok(())
+            }
+            Err(e) => {
+                eprintln!("⚠️  Failed to delete {}: {}", autostart_path.display(), e);
+                Err(e.to_string())
+            }
+        };
+        report.add(format!("Delete {}", autostart_path.display()), result);
+    }
+    
+    // The setup is gone, so the next install starts from scratch and a
+    // later rollback does not act on the runs undone here
+    if !report.has_failures() {
+        for run in &mut removal.runs {
+            report.add(format!("Mark run {} as rolled back", run.id), run.mark_rolled_back());
+        }
+        if fs::remove_file(state::path()).is_ok() {
+            logfile::record(&format!("Deleted {}", state::path().display()));
         }
-        Err(_) => config.audio = None,
     }
+    
+    report.print("Uninstall Results");
+    if report.has_failures() {
+        std::process::exit(1);
+    }
+    println!("\n Uninstall complete.");
 }
 
-fn select_xdg_portal(config: &mut InstallConfig) {
-    println!("\n═══ Step 10: XDG Desktop Portal ═══");
+/// Backs up hyprland.conf and writes `content`, the config without the
+/// auto-generated section.
+fn strip_config(config_path: &Path, content: &str) -> Result<(), String> {
+    let backup_path = config_path.with_extension("conf.backup");
+    if let Err(e) = fs::copy(config_path, &backup_path) {
+        eprintln!("⚠️  Failed to create backup: {}", e);
+        return Err(format!("backup failed: {}", e));
+    }
+    println!("✓ Backed up existing config to: {}", backup_path.display());
     
-    match Confirm::new("Install XDG Desktop Portal (xdg-desktop-portal-hyprland)?")
-        .with_default(true)
-        .prompt()
-    {
-        Ok(answer) => config.xdg_portal = answer,
-        Err(_) => config.xdg_portal = false,
+    match fs::write(config_path, content) {
+        Ok(_) => {
+            println!("✓ Removed auto-generated section from: {}", config_path.display());
+            logfile::record(&format!("Removed auto-generated section from {}", config_path.display()));
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("⚠️  Failed to write config file: {}", e);
+            Err(e.to_string())
+        }
     }
 }
 
-fn select_auth_agent(config: &mut InstallConfig) {
-    println!("\n═══ Step 11: Authentication Agent ═══");
-    
-    let options = vec!["hyprpolkitagent (default)", "polkit-kde-agent", "polkit-gnome", "SKIP"];
+fn run_rollback(run: Option<u64>) {
+    println!("═══ Rollback ═══\n");
     
-    match Select::new("Select authentication agent:", options).prompt() {
-        Ok(choice) => {
-            config.auth_agent = match choice {
-                "SKIP" => None,
-                option => Some(option.split_whitespace().next().unwrap().to_string()),
-            };
+    let mut record = match rollback::load(run) {
+        Ok(record) => record,
+        Err(e) => {
+            eprintln!("❌ {}", e);
+            std::process::exit(1);
         }
-        Err(_) => config.auth_agent = None,
+    };
+    
+    record.print();
+    
+    if !options().dry_run && !confirm("\nUndo these changes?", true) {
+        println!("\n❌ Rollback cancelled.");
+        return;
+    }
+    
+    let report = record.roll_back(options().dry_run);
+    report.print(if options().dry_run { "Dry Run Results" } else { "Rollback Results" });
+    
+    if options().dry_run {
+        println!("\n DRY RUN complete! No changes were made to your system.");
+    } else if report.has_failures() {
+        println!("\n Rollback of run {} finished with failures; it stays recorded so you can run it again.", record.id);
+        std::process::exit(1);
+    } else {
+        println!("\n Rollback of run {} complete.", record.id);
     }
 }
 
-fn select_qt_support(config: &mut InstallConfig) {
-    println!("\n═══ Step 12: Qt5/6 Support ═══");
-    
-    match Confirm::new("Install Qt5/Qt6 Wayland support?")
-        .with_default(true)
-        .prompt()
-    {
-        Ok(answer) => config.qt_support = answer,
-        Err(_) => config.qt_support = false,
+/// Removes the auto-generated exec-once section (and the blank lines the
+/// installer put in front of it). Returns `None` if there is no such section.
+fn strip_generated_section(content: &str) -> Option<String> {
+    let start_idx = content.find(MARKER_START)?;
+    let end_idx = content[start_idx..].find(MARKER_END)? + start_idx + MARKER_END.len();
+    
+    let before = content[..start_idx].trim_end_matches('\n');
+    let after = content[end_idx..].trim_start_matches('\n');
+    
+    let mut stripped = before.to_string();
+    if !after.is_empty() {
+        stripped.push_str("\n\n");
+        stripped.push_str(after);
+    } else {
+        stripped.push('\n');
     }
+    Some(stripped)
 }
 
-fn select_status_bar(config: &mut InstallConfig) {
-    println!("\n═══ Step 13: Status Bar ═══");
-    
-    let options = vec!["waybar (default)", "polybar", "eww", "ironbar", "SKIP"];
-    
-    match Select::new("Select a status bar:", options).prompt() {
-        Ok(choice) => {
-            config.status_bar = match choice {
-                "SKIP" => None,
-                option => Some(option.split_whitespace().next().unwrap().to_string()),
-            };
-        }
-        Err(_) => config.status_bar = None,
+fn command_exists(command: &str) -> bool {
+    runner()
+        .query(&Cmd::new("which").arg(command))
+        .map(|o| o.status.success())
+        .unwrap_or(false)
+}
+
+fn check_root() -> bool {
+    match runner().query(&Cmd::new("id").arg("-u")) {
+        Ok(output) => String::from_utf8_lossy(&output.stdout).trim() == "0",
+        Err(_) => false,
     }
 }
 
-fn select_wallpaper_utils(config: &mut InstallConfig) {
-    println!("\n═══ Step 14: Wallpaper Utility (Multiple Selection) ═══");
-    
-    let options = vec![
-        "hyprpaper",
-        "waypaper",
-        "swww",
-        "swaybg",
-        "mpvpaper",
-        "wpaperd",
-    ];
+fn select_aur_helper(config: &mut InstallConfig, step: &Step, preselect: Option<&[String]>, can_go_back: bool) -> StepAction {
+    // Check if yay or paru is already installed
+    let yay_installed = command_exists("yay");
+    let paru_installed = command_exists("paru");
     
-    match MultiSelect::new("Select wallpaper utilities (Space to select, Enter to confirm):", options).prompt() {
-        Ok(choices) => {
-            config.wallpaper_utils = choices.iter().map(|s| s.to_string()).collect();
+    if yay_installed || paru_installed {
+        let installed = if yay_installed { "yay" } else { "paru" };
+        println!("✓ AUR helper already installed: {}", installed);
+        
+        if confirm(&format!("Keep using {}?", installed), true) {
+            config.aur_helper = Some(installed.to_string());
+            return StepAction::Next;
         }
-        Err(_) => config.wallpaper_utils = Vec::new(),
     }
+    
+    select_step(config, step, preselect, can_go_back)
 }
 
-fn select_app_launcher(config: &mut InstallConfig) {
-    println!("\n═══ Step 15: Application Launcher ═══");
+/// Shows the GPUs found on the PCI bus and preselects the matching driver,
+/// unless a previous choice is given.
+fn select_gpu_driver(config: &mut InstallConfig, step: &Step, preselect: Option<&[String]>, can_go_back: bool) -> StepAction {
+    let gpus = gpu::detect_gpus(&options().sysfs_root);
     
-    let options = vec!["rofi (default)", "wofi", "tofi", "fuzzel", "bemenu", "anyrun", "walker", "SKIP"];
-    
-    match Select::new("Select an application launcher:", options).prompt() {
-        Ok(choice) => {
-            config.app_launcher = match choice {
-                "SKIP" => None,
-                option => Some(option.split_whitespace().next().unwrap().to_string()),
-            };
-        }
-        Err(_) => config.app_launcher = None,
+    if gpus.is_empty() {
+        println!("ℹ️  No GPU detected");
+    }
+    for gpu in &gpus {
+        println!("✓ Detected: {}", gpu);
     }
+    
+    let suggested: Vec<String> = gpu::suggested_driver(&gpus).into_iter().map(str::to_string).collect();
+    let preselect = preselect.or((!suggested.is_empty()).then_some(&suggested[..]));
+    select_step(config, step, preselect, can_go_back)
 }
 
-fn select_color_picker(config: &mut InstallConfig) {
-    println!("\n═══ Step 16: Color Picker ═══");
+/// Warns when the chosen GPU driver does not match the detected hardware.
+fn check_gpu_driver(config: &InstallConfig) {
+    let driver = match config.gpu_driver {
+        Some(ref driver) => driver,
+        None => return,
+    };
     
-    let options = vec!["hyprpicker (default)", "wl-color-picker", "SKIP"];
+    let gpus = gpu::detect_gpus(&options().sysfs_root);
+    if let Some(problem) = gpu::driver_mismatch(&gpus, driver) {
+        println!("⚠️  GPU driver mismatch: {}", problem);
+        logfile::record(&format!("GPU driver mismatch: {}", problem));
+    }
     
-    match Select::new("Select a color picker:", options).prompt() {
-        Ok(choice) => {
-            config.color_picker = match choice {
-                "SKIP" => None,
-                option => Some(option.split_whitespace().next().unwrap().to_string()),
-            };
-        }
-        Err(_) => config.color_picker = None,
+    if config.hybrid_graphics && hybrid_gpus(config).is_none() {
+        println!("⚠️  Hybrid graphics was chosen, but no NVIDIA card next to an integrated GPU was found");
     }
 }
 
-fn select_clipboard_manager(config: &mut InstallConfig) {
-    println!("\n═══ Step 17: Clipboard Manager ═══");
-    
-    let options = vec!["cliphist (default)", "clipman", "clipse", "copyq", "wl-clip-persist", "SKIP"];
-    
-    match Select::new("Select a clipboard manager:", options).prompt() {
-        Ok(choice) => {
-            config.clipboard_manager = match choice {
-                "SKIP" => None,
-                option => Some(option.split_whitespace().next().unwrap().to_string()),
-            };
-        }
-        Err(_) => config.clipboard_manager = None,
+/// The (integrated, NVIDIA) GPU pair when hybrid mode applies to `config`.
+fn hybrid_gpus(config: &InstallConfig) -> Option<(gpu::Gpu, gpu::Gpu)> {
+    if config.gpu_driver.as_deref() != Some("nvidia") {
+        return None;
     }
+    gpu::hybrid_pair(&gpu::detect_gpus(&options().sysfs_root))
 }
 
-fn select_file_manager(config: &mut InstallConfig) {
-    println!("\n═══ Step 18: File Manager ═══");
-    
-    // GUI File Manager
-    let gui_options = vec!["dolphin (default)", "nautilus", "nemo", "thunar", "SKIP"];
-    
-    match Select::new("Select GUI file manager:", gui_options).prompt() {
-        Ok(choice) => {
-            config.gui_file_manager = match choice {
-                "SKIP" => None,
-                option => Some(option.split_whitespace().next().unwrap().to_string()),
+/// Fills in the catalog placeholders in `text`. Returns `None` when one of
+/// them has no value for this system or `config`.
+fn expand_placeholders(text: &str, config: &InstallConfig) -> Option<String> {
+    if !text.contains('{') {
+        return Some(text.to_string());
+    }
+    
+    let mut result = text.to_string();
+    if result.contains("{hypr_dir}") {
+        let hypr_dir = get_user_config_dir()?.join("hypr");
+        result = result.replace("{hypr_dir}", &hypr_dir.to_string_lossy());
+    }
+    if result.contains("{launcher}") {
+        let launcher = config
+            .app_launcher
+            .as_ref()
+            .and_then(|id| catalog().component("app_launcher", id))
+            .and_then(|c| c.launch_command.clone())?;
+        result = result.replace("{launcher}", &launcher);
+    }
+    if ["{igpu_card}", "{dgpu_card}", "{igpu_link}", "{dgpu_link}"].iter().any(|p| result.contains(p)) {
+        let (igpu, dgpu) = hybrid_gpus(config)?;
+        let hypr_dir = get_user_config_dir()?.join("hypr");
+        result = result
+            .replace("{igpu_card}", &igpu.by_path().to_string_lossy())
+            .replace("{dgpu_card}", &dgpu.by_path().to_string_lossy())
+            .replace("{igpu_link}", &hypr_dir.join("igpu").to_string_lossy())
+            .replace("{dgpu_link}", &hypr_dir.join("dgpu").to_string_lossy());
+    }
+    Some(result)
+}
+
+/// Label of the extra option that returns to the previous wizard step.
+const BACK_LABEL: &str = "← Back";
+
+/// Prompts for one catalog step and stores the answer in `config`. The
+/// components in `preselect` start out highlighted (or checked); an empty
+/// list starts on "skip" or "no", and `None` on the installed components,
+/// or the catalog's default when none is installed.
+/// With `can_go_back`, the prompt also offers "← Back", which leaves
+/// `config` as it was.
+fn select_step(config: &mut InstallConfig, step: &Step, preselect: Option<&[String]>, can_go_back: bool) -> StepAction {
+    let components: Vec<&Component> = catalog().components_for(&step.key).collect();
+    let mut labels: Vec<String> = components.iter().map(|c| c.prompt_label()).collect();
+    
+    let installed: Vec<String> = components.iter().filter(|c| c.is_installed()).map(|c| c.id.clone()).collect();
+    let preselect = preselect.or((!installed.is_empty()).then_some(&installed[..]));
+    let preselected: Vec<usize> = components
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| preselect.is_some_and(|ids| ids.contains(&c.id)))
+        .map(|(i, _)| i)
+        .collect();
+    
+    // Every option past the components is "skip" (for single steps) or "← Back"
+    let selection = match step.kind {
+        StepKind::Single => {
+            labels.push(step.skip_label().to_string());
+            if can_go_back {
+                labels.push(BACK_LABEL.to_string());
+            }
+            
+            let cursor = match preselect {
+                Some([]) => components.len(),
+                _ => preselected.first().copied().unwrap_or(0),
             };
+            match Select::new(&step.prompt, labels).with_starting_cursor(cursor).raw_prompt() {
+                Ok(choice) if choice.index > components.len() => return StepAction::Back,
+                Ok(choice) => components.get(choice.index).map(|c| vec![c.id.clone()]).unwrap_or_default(),
+                Err(_) => Vec::new(),
+            }
         }
-        Err(_) => config.gui_file_manager = None,
-    }
-    
-    // TUI File Manager
-    let tui_options = vec!["lf", "nnn", "ranger", "yazi", "SKIP"];
-    
-    match Select::new("Select TUI file manager:", tui_options).prompt() {
-        Ok(choice) => {
-            config.tui_file_manager = match choice {
-                "SKIP" => None,
-                option => Some(option.to_string()),
+        StepKind::Multi => {
+            if can_go_back {
+                labels.push(BACK_LABEL.to_string());
+            }
+            
+            match MultiSelect::new(&step.prompt, labels).with_default(&preselected).raw_prompt() {
+                Ok(choices) if choices.iter().any(|choice| choice.index >= components.len()) => return StepAction::Back,
+                Ok(choices) => choices.iter().map(|choice| components[choice.index].id.clone()).collect(),
+                Err(_) => Vec::new(),
+            }
+        }
+        StepKind::Toggle => {
+            let default = preselect.map_or(step.default_on, |ids| !ids.is_empty());
+            // The one option has no list to go in, so it is shown as the help line
+            let help = labels.join(", ");
+            let on = if can_go_back {
+                let options = vec!["Yes", "No", BACK_LABEL];
+                match Select::new(&step.prompt, options).with_starting_cursor(if default { 0 } else { 1 }).with_help_message(&help).raw_prompt() {
+                    Ok(choice) if choice.index == 2 => return StepAction::Back,
+                    Ok(choice) => choice.index == 0,
+                    Err(_) => false,
+                }
+            } else {
+                Confirm::new(&step.prompt).with_default(default).with_help_message(&help).prompt().unwrap_or(false)
             };
+            
+            if on {
+                components.iter().map(|c| c.id.clone()).collect()
+            } else {
+                Vec::new()
+            }
         }
-        Err(_) => config.tui_file_manager = None,
-    }
+    };
+    
+    config.set_selection(&step.key, selection);
+    StepAction::Next
 }
 
 fn display_summary(config: &InstallConfig) {
@@ -491,323 +915,551 @@ fn display_summary(config: &InstallConfig) {
     println!("║           Installation Summary               ║");
     println!("╚══════════════════════════════════════════════╝\n");
     
-    if config.dry_run {
+    if options().dry_run {
         println!("MODE:                 DRY RUN (No changes will be made)\n");
     }
     
-    println!("AUR Helper:            {:?}", config.aur_helper.as_ref().unwrap_or(&"None".to_string()));
-    println!("Display Manager:       {:?}", config.greeter.as_ref().unwrap_or(&"None".to_string()));
-    println!("GPU Driver:            {:?}", config.gpu_driver.as_ref().unwrap_or(&"None".to_string()));
-    println!("Hyprland Version:      {:?}", config.hyprland_version.as_ref().unwrap_or(&"None".to_string()));
-    println!("XDG User Directories:  {}", if config.xdg_user_dirs { "Yes" } else { "No" });
-    println!("UWSM:                  {}", if config.uwsm { "Yes" } else { "No" });
-    println!("Terminal:              {:?}", config.terminal.as_ref().unwrap_or(&"None".to_string()));
-    println!("Shell:                 {:?}", config.shell.as_ref().unwrap_or(&"None".to_string()));
-    println!("Notification Daemon:   {:?}", config.notification_daemon.as_ref().unwrap_or(&"None".to_string()));
-    println!("Audio System:          {:?}", config.audio.as_ref().unwrap_or(&"None".to_string()));
-    println!("XDG Portal:            {}", if config.xdg_portal { "Yes" } else { "No" });
-    println!("Auth Agent:            {:?}", config.auth_agent.as_ref().unwrap_or(&"None".to_string()));
-    println!("Qt Support:            {}", if config.qt_support { "Yes" } else { "No" });
-    println!("Status Bar:            {:?}", config.status_bar.as_ref().unwrap_or(&"None".to_string()));
-    println!("Wallpaper Utils:       {:?}", if config.wallpaper_utils.is_empty() { "None".to_string() } else { config.wallpaper_utils.join(", ") });
-    println!("App Launcher:          {:?}", config.app_launcher.as_ref().unwrap_or(&"None".to_string()));
-    println!("Color Picker:          {:?}", config.color_picker.as_ref().unwrap_or(&"None".to_string()));
-    println!("Clipboard Manager:     {:?}", config.clipboard_manager.as_ref().unwrap_or(&"None".to_string()));
-    println!("GUI File Manager:      {:?}", config.gui_file_manager.as_ref().unwrap_or(&"None".to_string()));
-    println!("TUI File Manager:      {:?}", config.tui_file_manager.as_ref().unwrap_or(&"None".to_string()));
+    for step in &catalog().steps {
+        println!("{}", summary_row(step, config));
+    }
     println!();
 }
 
-fn confirm_installation(config: &InstallConfig) -> bool {
-    let prompt = if config.dry_run {
+/// One line of the summary, e.g. `Status Bar:            "waybar"`.
+fn summary_row(step: &Step, config: &InstallConfig) -> String {
+    let selection = config.selection(&step.key);
+    let label = format!("{}:", step.name);
+    
+    match step.kind {
+        StepKind::Toggle => format!("{:<22} {}", label, if selection.is_empty() { "No" } else { "Yes" }),
+        _ => {
+            let value = if selection.is_empty() { "None".to_string() } else { selection.join(", ") };
+            format!("{:<22} {:?}", label, value)
+        }
+    }
+}
+
+/// Lets the user pick a summary row to change before going on. Returns
+/// `false` once they are happy with every choice.
+fn edit_from_summary(config: &mut InstallConfig) -> bool {
+    if options().yes {
+        return false;
+    }
+    
+    let steps: Vec<&Step> = catalog().steps.iter().filter(|step| step_applies(step, config)).collect();
+    let mut rows = vec!["✓ Continue with these choices".to_string()];
+    rows.extend(steps.iter().map(|step| summary_row(step, config)));
+    
+    let choice = match Select::new("Change a choice before continuing?", rows).with_page_size(12).raw_prompt() {
+        Ok(choice) if choice.index > 0 => choice.index - 1,
+        _ => return false,
+    };
+    
+    let step = steps[choice];
+    let current = config.selection(&step.key);
+    let gpu_choice = (config.gpu_driver.clone(), config.hybrid_graphics);
+    let mut shown_header = step.number;
+    println!("\n═══ Step {}: {} ═══", step.number, step.title);
+    match step.key.as_str() {
+        "gpu_driver" => select_gpu_driver(config, step, Some(&current), false),
+        _ => select_step(config, step, Some(&current), false),
+    };
+    
+    // A changed answer can make a conditional step stop applying, or start
+    // applying (e.g. hybrid graphics after switching to NVIDIA), in which
+    // case it is asked now; steps are checked in order, so an answer given
+    // here can bring in a later step too
+    for later in &catalog().steps {
+        if !step_applies(later, config) {
+            config.set_selection(&later.key, Vec::new());
+        } else if !steps.iter().any(|s| s.key == later.key) {
+            if shown_header != later.number {
+                println!("\n═══ Step {}: {} ═══", later.number, later.title);
+                if let Some(ref description) = later.description {
+                    println!("{}", description);
+                }
+                shown_header = later.number;
+            }
+            select_step(config, later, None, false);
+        }
+    }
+    
+    if (config.gpu_driver.clone(), config.hybrid_graphics) != gpu_choice {
+        check_gpu_driver(config);
+    }
+    true
+}
+
+fn offer_save_answers(config: &InstallConfig) {
+    if !confirm("Save these answers to a file for reuse with --answers?", false) {
+        return;
+    }
+    
+    let path = match Text::new("Answer file path (.toml or .json):")
+        .with_default("hyprland-answers.toml")
+        .prompt()
+    {
+        Ok(path) => path,
+        Err(_) => return,
+    };
+    
+    match AnswerFile::from_config(config).save(Path::new(&path)) {
+        Ok(_) => println!("✓ Saved answers to: {}\n", path),
+        Err(e) => eprintln!("⚠️  {}\n", e),
+    }
+}
+
+fn confirm_installation() -> bool {
+    let prompt = if options().dry_run {
         "Continue with dry run?"
     } else {
         "Proceed with installation?"
     };
     
-    match Confirm::new(prompt)
-        .with_default(true)
-        .prompt()
-    {
-        Ok(answer) => answer,
-        Err(_) => false,
-    }
+    confirm(prompt, true)
+}
+
+fn display_plan(plan: &InstallPlan) {
+    println!("╔══════════════════════════════════════════════╗");
+    println!("║             Installation Plan                ║");
+    println!("╚══════════════════════════════════════════════╝\n");
+    
+    plan.print();
+    println!();
 }
 
-fn perform_installation(config: &InstallConfig) {
-    if config.dry_run {
-        println!("\n DRY RUN: Showing what would be installed...\n");
+/// Carries out an `InstallPlan`, phase by phase. Phases the checkpoint
+/// has as done are skipped, and progress is saved after each phase that
+/// finishes without failures. In dry run mode every phase still runs, but
+/// through the recording runner: commands are printed instead of executed,
+/// files are not written and no progress is saved. Every phase ends up in
+/// the returned report.
+fn perform_installation(config: &InstallConfig, plan: &InstallPlan, mut checkpoint: Checkpoint) -> Report {
+    let mut report = Report::default();
+    
+    if options().dry_run {
+        println!("\n DRY RUN: Commands that would be executed...\n");
     } else {
         println!("\n Starting installation...\n");
-    }
-    
-    // Install AUR helper first if needed
-    if let Some(ref aur_helper) = config.aur_helper {
-        if config.dry_run {
-            println!(" Would install AUR helper: {}", aur_helper);
-        } else {
-            install_aur_helper(aur_helper);
+        match checkpoint.run.take() {
+            Some(record) => rollback::resume(record),
+            None => rollback::start(),
         }
+        checkpoint.update();
     }
     
-    let mut packages = Vec::new();
-    let mut aur_packages = Vec::new();
-    let mut services_to_enable = Vec::new();
-    
-    // Collect packages based on configuration
-    if let Some(ref greeter) = config.greeter {
-        packages.push(greeter.as_str());
-        services_to_enable.push(format!("{}.service", greeter));
-    }
-    
-    if let Some(ref gpu) = config.gpu_driver {
-        match gpu.as_str() {
-            "nvidia" => {
-                packages.extend_from_slice(&["nvidia", "nvidia-utils", "nvidia-settings"]);
-            }
-            "amd" => {
-                packages.extend_from_slice(&["mesa", "vulkan-radeon", "libva-mesa-driver"]);
-            }
-            "intel" => {
-                packages.extend_from_slice(&["mesa", "vulkan-intel", "intel-media-driver"]);
-            }
-            "open-source (mesa)" => {
-                packages.push("mesa");
-            }
-            _ => {}
+    for phase in Phase::ALL {
+        if checkpoint.is_done(phase) {
+            report.skip(phase.title(), "done in the earlier run");
+            continue;
         }
-    }
-    
-    if let Some(ref hypr) = config.hyprland_version {
-        if hypr == "hyprland" {
-            packages.push("hyprland");
+        
+        let failures = report.failures();
+        run_phase(phase, plan, &mut report);
+        if report.failures() == failures {
+            checkpoint.complete(phase);
         } else {
-            aur_packages.push(hypr.as_str());
+            checkpoint.update();
         }
     }
     
-    if config.xdg_user_dirs {
-        packages.push("xdg-user-dirs");
+    report.print(if options().dry_run { "Dry Run Results" } else { "Installation Results" });
+    
+    if options().dry_run {
+        println!("\n DRY RUN complete! {} command(s) recorded, no changes were made to your system.", runner::recorded_commands().len());
+        println!("\n To perform actual installation:");
+        println!("   Run the installer again without --dry-run and answer 'No' to dry run mode");
+        print_log_path();
+        return report;
     }
     
-    if config.uwsm {
-        aur_packages.push("uwsm");
+    if report.has_failures() {
+        println!("\n Installation finished with failures; see the results above.");
+        println!(" Run the installer again to retry the phases that failed.");
+    } else {
+        println!("\n Installation complete!");
+        checkpoint::clear();
     }
     
-    if let Some(ref term) = config.terminal {
-        packages.push(term.as_str());
+    // Saved as part of the run, so rolling it back also restores the old state
+    rollback::record_file(&state::path());
+    if let Err(e) = state::save(config, plan) {
+        eprintln!("⚠️  Could not save the installer state: {}", e);
     }
     
-    if let Some(ref shell) = config.shell {
-        packages.push(shell.as_str());
+    if let Some(id) = rollback::finish() {
+        println!("\n Changes recorded as run {}. Undo them with: hyprland-installer rollback", id);
     }
     
-    if let Some(ref notif) = config.notification_daemon {
-        packages.push(notif.as_str());
+    match active_display_manager() {
+        Some(service) => println!("\n Display manager: {}", service),
+        None => println!("\n Display manager: none enabled (start Hyprland from a TTY)"),
     }
     
-    if let Some(ref audio) = config.audio {
-        match audio.as_str() {
-            "pipewire" => {
-                packages.extend_from_slice(&["pipewire", "pipewire-pulse", "pipewire-alsa", "pipewire-jack", "wireplumber"]);
+    println!("\n Next steps:");
+    println!("   1. Review your hyprland.conf at ~/.config/hypr/hyprland.conf");
+    println!("   2. Adjust any exec-once paths or parameters as needed");
+    println!("   3. Configure wallpaper paths and other personal preferences");
+    println!("   4. Reboot your system");
+    println!("   5. Select Hyprland from your display manager");
+    println!("\n Documentation: https://wiki.hyprland.org/");
+    print_log_path();
+    
+    report
+}
+
+/// Runs one phase of `plan`, adding the outcome of each of its steps to `report`.
+fn run_phase(phase: Phase, plan: &InstallPlan, report: &mut Report) {
+    match phase {
+        // Install AUR helper first if needed
+        Phase::AurHelper => match plan.aur_helper {
+            Some(ref aur_helper) => report.add(format!("AUR helper ({})", aur_helper), install_aur_helper(aur_helper)),
+            None => report.skip("AUR helper", "none chosen"),
+        },
+        
+        // Install official repo packages
+        Phase::RepoPackages => {
+            if plan.repo_packages.is_empty() {
+                report.skip("Repository packages", "nothing to install");
+            } else {
+                println!("\n Installing packages from official repositories...");
+                let result = install_replacing(plan, &plan.repo_packages, || install_pacman_packages(runner(), pacman::sync_db(), &plan.repo_packages));
+                report.add("Repository packages", result);
             }
-            "pulseaudio" => {
-                packages.extend_from_slice(&["pulseaudio", "pulseaudio-alsa"]);
+        }
+        
+        // Install AUR packages
+        Phase::AurPackages => {
+            if plan.aur_packages.is_empty() {
+                report.skip("AUR packages", "nothing to install");
+            } else {
+                println!("\n Installing AUR packages...");
+                let result = install_replacing(plan, &plan.aur_packages, || {
+                    install_aur_packages(&plan.aur_packages, plan.aur_helper.as_deref())
+                });
+                report.add("AUR packages", result);
             }
-            _ => {}
         }
-    }
-    
-    if config.xdg_portal {
-        packages.extend_from_slice(&["xdg-desktop-portal-hyprland", "xdg-desktop-portal"]);
-    }
-    
-    if let Some(ref auth) = config.auth_agent {
-        if auth == "hyprpolkitagent" {
-            packages.push("hyprpolkitagent");
-        } else {
-            packages.push(auth.as_str());
+        
+        // Disable services that would clash, such as another display manager,
+        // then enable the chosen ones
+        Phase::Services => {
+            for service in &plan.disable_services {
+                report.add(format!("Disable {}", service), disable_service(service));
+            }
+            for service in &plan.services {
+                report.add(format!("Enable {}", service), enable_service(service));
+            }
+        }
+        
+        // Commands that run as the regular user, e.g. xdg-user-dirs-update
+        Phase::UserCommands => {
+            for cmd in &plan.user_commands {
+                report.add(cmd.description.clone(), run_user_command(cmd));
+            }
+        }
+        
+        Phase::FileWrites => {
+            for write in &plan.file_writes {
+                report.add(format!("Write {}", write.path.display()), write_file(write));
+            }
+        }
+        
+        // Commands that pick up the written files, e.g. regenerating the initramfs
+        Phase::SystemCommands => {
+            for cmd in &plan.system_commands {
+                report.add(cmd.description.clone(), run_system_command(cmd));
+            }
         }
     }
-    
-    if config.qt_support {
-        packages.extend_from_slice(&["qt5-wayland", "qt6-wayland"]);
+}
+
+fn print_log_path() {
+    if let Some(path) = logfile::written() {
+        println!("\n Full command log: {}", path.display());
     }
-    
-    if let Some(ref bar) = config.status_bar {
-        packages.push(bar.as_str());
+}
+
+/// Reports a completed action. In a dry run nothing really happened, so
+/// only the recorded commands are shown.
+fn report_success(message: &str) {
+    if !options().dry_run {
+        println!("✓ {}", message);
+        logfile::record(message);
     }
+}
+
+fn run_user_command(cmd: &UserCommand) -> Result<(), String> {
+    println!("\n {}...", cmd.description);
     
-    for wallpaper in &config.wallpaper_utils {
-        if wallpaper == "hyprpaper" || wallpaper == "swww" {
-            packages.push(wallpaper.as_str());
-        } else {
-            aur_packages.push(wallpaper.as_str());
+    let (program, args) = cmd.argv.split_first().ok_or("empty command")?;
+    
+    match run_checked(&Cmd::as_user(&cmd.user, program).args(args)) {
+        Ok(()) => {
+            report_success(&format!("Ran as {}: {}", cmd.user, cmd.argv.join(" ")));
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("⚠️  Failed: {}", cmd.argv.join(" "));
+            logfile::record(&format!("Failed as {}: {}", cmd.user, cmd.argv.join(" ")));
+            Err(e)
         }
     }
+}
+
+fn run_system_command(cmd: &SystemCommand) -> Result<(), String> {
+    println!("\n {}...", cmd.description);
     
-    if let Some(ref launcher) = config.app_launcher {
-        packages.push(launcher.as_str());
-    }
+    let (program, args) = cmd.argv.split_first().ok_or("empty command")?;
     
-    if let Some(ref picker) = config.color_picker {
-        packages.push(picker.as_str());
+    match run_checked(&Cmd::new(program).args(args)) {
+        Ok(()) => {
+            report_success(&format!("Ran: {}", cmd.argv.join(" ")));
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("⚠️  Failed: {}", cmd.argv.join(" "));
+            logfile::record(&format!("Failed: {}", cmd.argv.join(" ")));
+            Err(e)
+        }
     }
-    
-    if let Some(ref clip) = config.clipboard_manager {
-        packages.push(clip.as_str());
-        // Most clipboard managers need wl-clipboard
-        if clip != "copyq" {
-            packages.push("wl-clipboard");
+}
+
+/// Runs `install` for `packages`, first removing the installed packages
+/// that some of them replace. If the install fails, the removed packages
+/// whose replacement did not get installed are put back, so e.g. a failed
+/// `hyprland-git` build does not leave the system without Hyprland.
+fn install_replacing(plan: &InstallPlan, packages: &[String], install: impl FnOnce() -> Result<(), String>) -> Result<(), String> {
+    let replacements: Vec<&Replacement> = plan
+        .replacements
+        .iter()
+        .filter(|r| packages.contains(&r.replaced_by))
+        .collect();
+    if replacements.is_empty() {
+        return install();
+    }
+    
+    println!(" Removing conflicting packages...");
+    remove_conflicting_packages(&replacements)?;
+    
+    let result = install();
+    if let Err(ref e) = result {
+        if let Err(restore) = restore_replaced(&replacements, plan.aur_helper.as_deref()) {
+            eprintln!("❌ Could not reinstall the removed packages: {}", restore);
+            return Err(format!("{}; removed packages not restored", e));
         }
     }
-    
-    if let Some(ref fm) = config.gui_file_manager {
-        packages.push(fm.as_str());
+    result
+}
+
+/// Reinstalls the packages removed for replacements that are not installed.
+fn restore_replaced(replacements: &[&Replacement], helper: Option<&str>) -> Result<(), String> {
+    let missing: Vec<String> = replacements
+        .iter()
+        .filter(|r| !package_installed(&r.replaced_by))
+        .map(|r| r.installed.clone())
+        .collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+    
+    println!("\n Reinstalling the packages removed for replacements that failed: {}", missing.join(" "));
+    logfile::record(&format!("Restoring replaced packages: {}", missing.join(" ")));
+    
+    let db = pacman::sync_db();
+    let (repo, aur): (Vec<String>, Vec<String>) = missing.into_iter().partition(|p| db.is_empty() || db.find(p).is_some());
+    if !repo.is_empty() {
+        run_checked(&pacman_install(&repo))?;
+    }
+    if !aur.is_empty() {
+        install_aur_packages(&aur, helper)?;
+    }
+    report_success("Removed packages reinstalled");
+    Ok(())
+}
+
+/// Whether `package` is installed right now (the cached local database may
+/// be out of date by the time this is asked).
+fn package_installed(package: &str) -> bool {
+    runner()
+        .query(&Cmd::new("pacman").args(["-Q", package]))
+        .is_ok_and(|output| output.status.success())
+}
+
+/// Removes installed packages that the plan replaces. Dependency checks are
+/// skipped because the replacements provide what other packages depend on.
+fn remove_conflicting_packages(replacements: &[&Replacement]) -> Result<(), String> {
+    for replacement in replacements {
+        println!("   {} → {}", replacement.installed, replacement.replaced_by);
     }
     
-    if let Some(ref fm) = config.tui_file_manager {
-        packages.push(fm.as_str());
-    }
+    let cmd = Cmd::new("pacman")
+        .args(["-Rdd", "--noconfirm"])
+        .args(replacements.iter().map(|r| &r.installed));
     
-    // Install official repo packages
-    if !packages.is_empty() {
-        if config.dry_run {
-            println!(" Would install from official repositories:");
-            for pkg in &packages {
-                println!("   - {}", pkg);
-            }
-        } else {
-            println!(" Installing packages from official repositories...");
-            install_pacman_packages(&packages);
+    match run_checked(&cmd) {
+        Ok(()) => {
+            report_success("Conflicting packages removed");
+            Ok(())
         }
-    }
-    
-    // Install AUR packages
-    if !aur_packages.is_empty() {
-        if config.dry_run {
-            println!("\n Would install from AUR:");
-            for pkg in &aur_packages {
-                println!("   - {}", pkg);
-            }
-        } else {
-            println!("\n Installing AUR packages...");
-            install_aur_packages(&aur_packages);
+        Err(e) => {
+            eprintln!("⚠️  Failed to remove conflicting packages; their replacements were not installed");
+            logfile::record("Failed to remove conflicting packages");
+            Err(e)
         }
     }
-    
-    // Enable services
-    if !services_to_enable.is_empty() {
-        if config.dry_run {
-            println!("\n Would enable services:");
-            for service in &services_to_enable {
-                println!("   - {}", service);
-            }
-        } else {
-            for service in services_to_enable {
-                enable_service(&service);
-            }
+}
+
+/// Removes `packages` together with the dependencies nothing else needs.
+fn remove_packages(packages: &[String]) -> Result<(), String> {
+    let cmd = Cmd::new("pacman")
+        .args(["-Rns", "--noconfirm"])
+        .args(packages);
+    
+    match run_checked(&cmd) {
+        Ok(()) => {
+            report_success(&format!("Removed packages: {}", packages.join(" ")));
+            Ok(())
         }
-    }
-    
-    // Initialize xdg-user-dirs if installed
-    if config.xdg_user_dirs {
-        if config.dry_run {
-            println!("\n Would initialize XDG user directories (Documents, Downloads, Pictures, etc.)");
-        } else {
-            println!("\n Initializing XDG user directories...");
-            if let Some(username) = get_username() {
-                let status = Command::new("sudo")
-                    .arg("-u")
-                    .arg(&username)
-                    .arg("xdg-user-dirs-update")
-                    .status();
-                
-                match status {
-                    Ok(s) if s.success() => println!("✓ XDG user directories created"),
-                    _ => eprintln!("⚠️  Failed to create XDG user directories"),
-                }
-            }
+        Err(e) => {
+            eprintln!("⚠️  Failed to remove packages: {}", packages.join(" "));
+            logfile::record(&format!("Failed to remove packages: {}", packages.join(" ")));
+            Err(e)
         }
     }
+}
+
+fn pacman_install(packages: &[String]) -> Cmd {
+    Cmd::new("pacman")
+        .args(["-S", "--needed", "--noconfirm"])
+        .args(packages)
+}
+
+/// Installs `packages` in one pacman transaction. If that fails, names
+/// missing from the sync database are set aside and the rest is retried
+/// in halves until every package that can be installed is.
+fn install_pacman_packages(runner: &dyn CommandRunner, db: &PackageDb, packages: &[String]) -> Result<(), String> {
+    let error = match runner.run_checked(&pacman_install(packages)) {
+        Ok(()) => {
+            report_success("Packages installed successfully");
+            return Ok(());
+        }
+        Err(e) => e,
+    };
     
-    if config.dry_run {
-        println!("\n DRY RUN complete! No changes were made to your system.");
+    eprintln!("⚠️  Installing the packages failed ({}); looking for the ones at fault...", error);
+    logfile::record(&format!("Failed to install packages: {}", packages.join(" ")));
+    
+    let (known, unknown): (Vec<String>, Vec<String>) = if db.is_empty() {
+        (packages.to_vec(), Vec::new())
     } else {
-        println!("\n Installation complete!");
+        packages.iter().cloned().partition(|p| db.find(p).is_some())
+    };
+    
+    let mut installed = Vec::new();
+    let mut failed: Vec<(String, String)> = unknown
+        .into_iter()
+        .map(|p| (p, "not in the sync database".to_string()))
+        .collect();
+    
+    // Without unknown names the same batch would fail again, so split it right away
+    if failed.is_empty() && known.len() > 1 {
+        let (first, second) = known.split_at(known.len() / 2);
+        install_bisect(runner, first, &mut installed, &mut failed);
+        install_bisect(runner, second, &mut installed, &mut failed);
+    } else if !failed.is_empty() {
+        install_bisect(runner, &known, &mut installed, &mut failed);
+    } else {
+        failed.extend(known.into_iter().map(|p| (p, error.clone())));
+    }
+    
+    if !installed.is_empty() {
+        report_success(&format!("Installed: {}", installed.join(", ")));
+    }
+    for (package, reason) in &failed {
+        eprintln!("❌ Could not install {}: {}", package, reason);
+        logfile::record(&format!("Could not install {}: {}", package, reason));
     }
     
-    if !config.dry_run {
-        println!("\n Next steps:");
-        println!("   1. Review your hyprland.conf at ~/.config/hypr/hyprland.conf");
-        println!("   2. Adjust any exec-once paths or parameters as needed");
-        println!("   3. Configure wallpaper paths and other personal preferences");
-        println!("   4. Reboot your system");
-        println!("   5. Select Hyprland from your display manager");
-        println!("\n Documentation: https://wiki.hyprland.org/");
+    if failed.is_empty() {
+        Ok(())
     } else {
-        println!("\n To perform actual installation:");
-        println!("   Run the installer again and answer 'No' to dry run mode");
+        let names: Vec<&str> = failed.iter().map(|(p, _)| p.as_str()).collect();
+        Err(format!("could not install {}", names.join(", ")))
     }
 }
 
-
-fn install_pacman_packages(packages: &[&str]) {
-    let status = Command::new("pacman")
-        .arg("-S")
-        .arg("--needed")
-        .arg("--noconfirm")
-        .args(packages)
-        .status();
+/// Installs `packages`, splitting the batch in two on failure until the
+/// packages that fail on their own are found.
+fn install_bisect(runner: &dyn CommandRunner, packages: &[String], installed: &mut Vec<String>, failed: &mut Vec<(String, String)>) {
+    if packages.is_empty() {
+        return;
+    }
     
-    match status {
-        Ok(status) if status.success() => {
-            println!("✓ Packages installed successfully");
-        }
-        _ => {
-            eprintln!("⚠️  Some packages may have failed to install");
+    match runner.run_checked(&pacman_install(packages)) {
+        Ok(()) => installed.extend_from_slice(packages),
+        Err(e) if packages.len() == 1 => failed.push((packages[0].clone(), e)),
+        Err(_) => {
+            let (first, second) = packages.split_at(packages.len() / 2);
+            install_bisect(runner, first, installed, failed);
+            install_bisect(runner, second, installed, failed);
         }
     }
 }
 
-fn install_aur_packages(packages: &[&str]) {
+/// Installs AUR packages with `helper`, or with whichever of yay/paru is
+/// already installed when no helper was chosen.
+fn install_aur_packages(packages: &[String], helper: Option<&str>) -> Result<(), String> {
     // Try yay first, then paru
-    let aur_helper = if Command::new("yay").arg("--version").output().is_ok() {
-        "yay"
-    } else if Command::new("paru").arg("--version").output().is_ok() {
-        "paru"
-    } else {
-        eprintln!("⚠️  No AUR helper found (yay/paru). Please install AUR packages manually:");
-        for pkg in packages {
-            eprintln!("   - {}", pkg);
+    let aur_helper = match helper {
+        Some(helper) => helper,
+        None if command_exists("yay") => "yay",
+        None if command_exists("paru") => "paru",
+        None => {
+            eprintln!("⚠️  No AUR helper found (yay/paru). Please install AUR packages manually:");
+            for pkg in packages {
+                eprintln!("   - {}", pkg);
+            }
+            return Err("no AUR helper found".to_string());
         }
-        return;
     };
     
-    let status = Command::new(aur_helper)
-        .arg("-S")
-        .arg("--needed")
-        .arg("--noconfirm")
-        .args(packages)
-        .status();
+    // AUR helpers refuse to run as root; they call sudo themselves for pacman
+    let username = match get_username() {
+        Some(user) if user != "root" => user,
+        _ => {
+            eprintln!("⚠️  AUR packages cannot be built as root. Please install them manually as a regular user:");
+            for pkg in packages {
+                eprintln!("   - {}", pkg);
+            }
+            return Err("AUR packages cannot be built as root".to_string());
+        }
+    };
     
-    match status {
-        Ok(status) if status.success() => {
-            println!("✓ AUR packages installed successfully");
+    let cmd = Cmd::as_user(&username, aur_helper)
+        .args(["-S", "--needed", "--noconfirm"])
+        .args(packages);
+    
+    match run_checked(&cmd) {
+        Ok(()) => {
+            report_success("AUR packages installed successfully");
+            Ok(())
         }
-        _ => {
+        Err(e) => {
             eprintln!("⚠️  Some AUR packages may have failed to install");
+            logfile::record(&format!("Failed to install AUR packages with {}: {}", aur_helper, packages.join(" ")));
+            Err(e)
         }
     }
 }
 
-fn install_aur_helper(helper: &str) {
+fn install_aur_helper(helper: &str) -> Outcome {
     println!(" Installing AUR helper: {}", helper);
     
     // Check if already installed
-    if Command::new("which").arg(helper).output().map(|o| o.status.success()).unwrap_or(false) {
+    if command_exists(helper) {
         println!("✓ {} is already installed", helper);
-        return;
+        return Outcome::Skipped("already installed".to_string());
     }
     
     // Get the actual username (not root)
@@ -815,24 +1467,22 @@ fn install_aur_helper(helper: &str) {
         Some(user) => user,
         None => {
             eprintln!("⚠️  Could not determine username. Please install {} manually.", helper);
-            return;
+            return Outcome::Failed("could not determine the user to build as".to_string());
         }
     };
     
     if username == "root" {
         eprintln!("⚠️  Cannot build AUR packages as root. Please install {} manually as a regular user.", helper);
-        return;
+        return Outcome::Failed("AUR packages cannot be built as root".to_string());
     }
     
     // Install base-devel and git if not present
     println!(" Installing build dependencies...");
-    let _ = Command::new("pacman")
-        .arg("-S")
-        .arg("--needed")
-        .arg("--noconfirm")
-        .arg("base-devel")
-        .arg("git")
-        .status();
+    if let Err(e) = run_checked(&Cmd::new("pacman").args(["-S", "--needed", "--noconfirm", "base-devel", "git"])) {
+        eprintln!("⚠️  Failed to install base-devel and git, which are needed to build {}", helper);
+        logfile::record(&format!("Failed to install build dependencies for {}", helper));
+        return Outcome::Failed(format!("could not install base-devel and git: {}", e));
+    }
     
     // Create temporary directory
     let temp_dir = format!("/tmp/{}-install", helper);
@@ -841,45 +1491,37 @@ fn install_aur_helper(helper: &str) {
     println!(" Cloning {} repository...", helper);
     
     // Clean up any existing directory
-    let _ = Command::new("rm").arg("-rf").arg(&temp_dir).status();
+    let _ = runner().run(&Cmd::new("rm").arg("-rf").arg(&temp_dir));
     
     // Clone as the regular user
-    let clone_status = Command::new("sudo")
-        .arg("-u")
-        .arg(&username)
-        .arg("git")
-        .arg("clone")
-        .arg(&repo_url)
-        .arg(&temp_dir)
-        .status();
-    
-    if clone_status.is_err() || !clone_status.unwrap().success() {
+    if let Err(e) = run_checked(&Cmd::as_user(&username, "git").arg("clone").arg(&repo_url).arg(&temp_dir)) {
         eprintln!("⚠️  Failed to clone {} repository", helper);
-        return;
+        return Outcome::Failed(e);
     }
     
     println!(" Building and installing {}...", helper);
     
     // Build and install as the regular user
-    let build_status = Command::new("sudo")
-        .arg("-u")
-        .arg(&username)
-        .arg("bash")
-        .arg("-c")
-        .arg(format!("cd {} && makepkg -si --noconfirm", temp_dir))
-        .status();
-    
-    match build_status {
-        Ok(status) if status.success() => {
-            println!(" {} installed successfully!", helper);
+    let build = run_checked(
+        &Cmd::as_user(&username, "makepkg")
+            .args(["-si", "--noconfirm"])
+            .current_dir(&temp_dir),
+    );
+    
+    match build {
+        Ok(()) => {
+            report_success(&format!("{} installed successfully!", helper));
             // Clean up
-            let _ = Command::new("rm").arg("-rf").arg(&temp_dir).status();
+            let _ = runner().run(&Cmd::new("rm").arg("-rf").arg(&temp_dir));
+            Outcome::Succeeded
         }
-        _ => {
+        Err(e) => {
             eprintln!("⚠️  Failed to build/install {}", helper);
+            logfile::record(&format!("Failed to build AUR helper: {}", helper));
             eprintln!("You can manually complete the installation:");
             eprintln!("   cd {}", temp_dir);
             eprintln!("   makepkg -si");
+            Outcome::Failed(e)
         }
     }
 }
@@ -904,25 +1546,50 @@ fn show_config_preview(config: &InstallConfig) {
     println!("# === AUTO-GENERATED EXEC-ONCE END ===\n");
 }
 
-fn enable_service(service: &str) {
+fn enable_service(service: &str) -> Result<(), String> {
     println!(" Enabling service: {}", service);
     
-    let status = Command::new("systemctl")
-        .arg("enable")
-        .arg(service)
-        .status();
-    
-    match status {
-        Ok(status) if status.success() => {
-            println!("✓ Service enabled: {}", service);
+    match run_checked(&Cmd::new("systemctl").arg("enable").arg(service)) {
+        Ok(()) => {
+            report_success(&format!("Service enabled: {}", service));
+            Ok(())
         }
-        _ => {
+        Err(e) => {
             eprintln!("⚠️  Failed to enable service: {}", service);
+            logfile::record(&format!("Failed to enable service: {}", service));
+            Err(e)
+        }
+    }
+}
+
+fn disable_service(service: &str) -> Result<(), String> {
+    println!(" Disabling service: {}", service);
+    
+    match run_checked(&Cmd::new("systemctl").arg("disable").arg(service)) {
+        Ok(()) => {
+            report_success(&format!("Service disabled: {}", service));
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("⚠️  Failed to disable service: {}", service);
+            logfile::record(&format!("Failed to disable service: {}", service));
+            Err(e)
         }
     }
 }
 
+/// The unit `display-manager.service` points at, e.g. `gdm.service`.
+fn active_display_manager() -> Option<String> {
+    let target = fs::read_link(DISPLAY_MANAGER_LINK).ok()?;
+    target.file_name().map(|name| name.to_string_lossy().into_owned())
+}
+
 fn get_username() -> Option<String> {
+    // An explicit --user always wins
+    if let Some(ref user) = options().user {
+        return Some(user.clone());
+    }
+    
     // Try to get username from SUDO_USER environment variable
     if let Ok(sudo_user) = std::env::var("SUDO_USER") {
         return Some(sudo_user);
@@ -936,13 +1603,11 @@ fn get_username() -> Option<String> {
     }
     
     // Ask the user
-    match Text::new("Enter your username (for config file location):").prompt() {
-        Ok(username) => Some(username),
-        Err(_) => None,
-    }
+    Text::new("Enter your username (for config file location):").prompt().ok()
 }
 
-fn get_hyprland_config_path() -> Option<PathBuf> {
+/// The target user's config directory (`$XDG_CONFIG_HOME` or `~/.config`).
+fn get_user_config_dir() -> Option<PathBuf> {
     let username = get_username()?;
     
     // Try XDG_CONFIG_HOME first
@@ -952,280 +1617,203 @@ fn get_hyprland_config_path() -> Option<PathBuf> {
         PathBuf::from(format!("/home/{}/.config", username))
     };
     
-    let hypr_config = config_path.join("hypr/hyprland.conf");
+    Some(config_path)
+}
+
+fn get_hyprland_config_path() -> Option<PathBuf> {
+    let hypr_config = get_user_config_dir()?.join("hypr/hyprland.conf");
     Some(hypr_config)
 }
 
-fn update_hyprland_config(config: &InstallConfig) {
-    println!("\n Updating hyprland.conf...\n");
+fn write_file(write: &FileWrite) -> Result<(), String> {
+    println!("\n Writing {}...\n", write.path.display());
     
-    let config_path = match get_hyprland_config_path() {
-        Some(path) => path,
-        None => {
-            eprintln!("⚠️  Could not determine config path");
-            return;
+    if options().dry_run {
+        println!("   Would write {} ({})", write.path.display(), write.description);
+        if let Some(ref backup_path) = write.backup {
+            println!("   Would back up the existing file to: {}", backup_path.display());
         }
-    };
+        fix_ownership(write);
+        return Ok(());
+    }
     
-    // Create hypr directory if it doesn't exist
-    if let Some(parent) = config_path.parent() {
+    // Create the parent directory if it doesn't exist
+    if let Some(parent) = write.path.parent() {
         if !parent.exists() {
             if let Err(e) = fs::create_dir_all(parent) {
-                eprintln!("⚠️  Failed to create config directory: {}", e);
-                return;
+                eprintln!("⚠️  Failed to create directory {}: {}", parent.display(), e);
+                return Err(format!("could not create {}: {}", parent.display(), e));
             }
         }
     }
     
-    // Check if config file exists
-    if !config_path.exists() {
-        println!("ℹ️  No existing hyprland.conf found at: {}", config_path.display());
-        println!("   Hyprland will create a default config on first run.");
-        println!("   The exec-once statements will be saved to: ~/.config/hypr/hyprland-autostart.conf");
-        println!("   You can include this in your main config with:");
-        println!("   source = ~/.config/hypr/hyprland-autostart.conf\n");
-        
-        // Create a separate autostart config file instead
-        let autostart_path = config_path.parent().unwrap().join("hyprland-autostart.conf");
-        let exec_statements = generate_exec_once_statements(config);
-        
-        if exec_statements.is_empty() {
-            println!("ℹ️  No exec-once statements to add");
-            return;
-        }
-        
-        let autostart_content = format!(
-            "# Auto-generated autostart configuration\n# Generated by hyprland-installer\n# Include this in your main hyprland.conf with: source = ~/.config/hypr/hyprland-autostart.conf\n\n{}\n",
-            exec_statements.join("\n")
-        );
-        
-        match fs::write(&autostart_path, autostart_content) {
-            Ok(_) => {
-                println!("✓ Created autostart config at: {}", autostart_path.display());
-                println!("\n   To use these settings, add this line to your hyprland.conf:");
-                println!("   source = ~/.config/hypr/hyprland-autostart.conf");
-                
-                // Fix ownership if running as root
-                if check_root() {
-                    if let Some(username) = get_username() {
-                        let _ = Command::new("chown")
-                            .arg(format!("{}:{}", username, username))
-                            .arg(&autostart_path)
-                            .status();
-                    }
-                }
-            }
-            Err(e) => {
-                eprintln!("⚠️  Failed to create autostart config: {}", e);
-            }
-        }
-        return;
-    }
+    rollback::record_file(&write.path);
     
-    // Read existing config
-    let mut config_content = {
-        // Backup existing config
-        let backup_path = config_path.with_extension("conf.backup");
-        if let Err(e) = fs::copy(&config_path, &backup_path) {
-            eprintln!("⚠️  Failed to create backup: {}", e);
-        } else {
-            println!("✓ Backed up existing config to: {}", backup_path.display());
-        }
-        
-        match fs::read_to_string(&config_path) {
-            Ok(content) => content,
-            Err(e) => {
-                eprintln!("⚠️  Failed to read config file: {}", e);
-                return;
+    // Backup the existing file
+    if let Some(ref backup_path) = write.backup {
+        if write.path.exists() {
+            if let Err(e) = fs::copy(&write.path, backup_path) {
+                eprintln!("⚠️  Failed to create backup: {}", e);
+            } else {
+                println!("✓ Backed up existing file to: {}", backup_path.display());
             }
         }
-    };
-    
-    // Generate exec-once statements
-    let exec_statements = generate_exec_once_statements(config);
-    
-    if exec_statements.is_empty() {
-        println!("ℹ️  No exec-once statements to add");
-        return;
     }
     
-    // Check for existing exec-once section
-    let marker_start = "# === AUTO-GENERATED EXEC-ONCE START ===";
-    let marker_end = "# === AUTO-GENERATED EXEC-ONCE END ===";
-    
-    if config_content.contains(marker_start) {
-        // Replace existing auto-generated section
-        let start_idx = config_content.find(marker_start).unwrap();
-        let end_idx = config_content.find(marker_end).unwrap() + marker_end.len();
-        
-        let new_section = format!(
-            "{}\n{}\n{}",
-            marker_start,
-            exec_statements.join("\n"),
-            marker_end
-        );
-        
-        config_content.replace_range(start_idx..end_idx, &new_section);
-    } else {
-        // Add new auto-generated section
-        config_content.push_str("\n\n");
-        config_content.push_str(marker_start);
-        config_content.push('\n');
-        config_content.push_str(&exec_statements.join("\n"));
-        config_content.push('\n');
-        config_content.push_str(marker_end);
-        config_content.push('\n');
-    }
-    
-    // Write updated config
-    match fs::write(&config_path, config_content) {
+    match fs::write(&write.path, &write.content) {
         Ok(_) => {
-            println!(" Successfully updated hyprland.conf at: {}", config_path.display());
-            println!("\n Added exec-once statements:");
-            for stmt in exec_statements {
-                println!("   {}", stmt);
-            }
+            println!("✓ Wrote {} ({})", write.path.display(), write.description);
+            logfile::record(&format!("Wrote {}", write.path.display()));
             
-            // Fix ownership if running as root
-            if check_root() {
-                if let Some(username) = get_username() {
-                    let _ = Command::new("chown")
-                        .arg("-R")
-                        .arg(format!("{}:{}", username, username))
-                        .arg(config_path.parent().unwrap())
-                        .status();
-                    println!("\n✓ Fixed file ownership for user: {}", username);
-                }
+            if let Some(ref note) = write.note {
+                println!("\n   {}", note);
             }
+            
+            fix_ownership(write);
+            Ok(())
         }
         Err(e) => {
-            eprintln!("⚠️  Failed to write config file: {}", e);
+            eprintln!("⚠️  Failed to write {}: {}", write.path.display(), e);
+            Err(e.to_string())
         }
     }
 }
 
+/// Hands a written file (and its directory) to the regular user when running as root.
+fn fix_ownership(write: &FileWrite) {
+    let owner = match write.owner {
+        Some(ref owner) => owner,
+        None => return,
+    };
+    
+    let ownership = format!("{}:{}", owner, owner);
+    let _ = runner().run(&Cmd::new("chown").arg(&ownership).arg(write.path.to_string_lossy()));
+    if let Some(parent) = write.path.parent() {
+        let _ = runner().run(&Cmd::new("chown").arg(&ownership).arg(parent.to_string_lossy()));
+    }
+    report_success(&format!("Fixed file ownership for user: {}", owner));
+}
+
 fn generate_exec_once_statements(config: &InstallConfig) -> Vec<String> {
+    let catalog = catalog();
     let mut statements = Vec::new();
     
-    // Add NVIDIA-specific environment variables if NVIDIA is selected
-    if let Some(ref gpu) = config.gpu_driver {
-        if gpu == "nvidia" {
-            statements.push("# NVIDIA-specific environment variables".to_string());
-            statements.push("env = LIBVA_DRIVER_NAME,nvidia".to_string());
-            statements.push("env = XDG_SESSION_TYPE,wayland".to_string());
-            statements.push("env = GBM_BACKEND,nvidia-drm".to_string());
-            statements.push("env = __GLX_VENDOR_LIBRARY_NAME,nvidia".to_string());
-            statements.push("".to_string());
-        }
-    }
-    
-    // Notification daemon
-    if let Some(ref notif) = config.notification_daemon {
-        statements.push("# Notification daemon".to_string());
-        let cmd = match notif.as_str() {
-            "dunst" => "exec-once = dunst",
-            "mako" => "exec-once = mako",
-            "fnott" => "exec-once = fnott",
-            "swaync" => "exec-once = swaync",
-            _ => "",
-        };
-        if !cmd.is_empty() {
-            statements.push(cmd.to_string());
-            statements.push("".to_string());
-        }
-    }
+    let hybrid = config.hybrid_graphics && hybrid_gpus(config).is_some();
     
-    // Audio system
-    if let Some(ref audio) = config.audio {
-        if audio == "pipewire" {
-            statements.push("# Audio system (PipeWire)".to_string());
-            statements.push("exec-once = /usr/bin/pipewire".to_string());
-            statements.push("exec-once = /usr/bin/pipewire-pulse".to_string());
-            statements.push("exec-once = /usr/bin/wireplumber".to_string());
-            statements.push("".to_string());
+    for step in &catalog.steps {
+        let components: Vec<&Component> = config
+            .selection(&step.key)
+            .iter()
+            .filter_map(|id| catalog.component(&step.key, id))
+            .collect();
+        
+        let mut lines = Vec::new();
+        for component in &components {
+            // The NVIDIA variables would pull rendering off the iGPU in hybrid mode
+            if !(hybrid && step.key == "gpu_driver") {
+                for env in &component.env {
+                    lines.extend(expand_placeholders(env, config).map(|env| format!("env = {}", env)));
+                }
+            }
+            for cmd in &component.exec_once {
+                lines.extend(expand_placeholders(cmd, config).map(|cmd| format!("exec-once = {}", cmd)));
+            }
+            for bind in &component.binds {
+                lines.extend(expand_placeholders(bind, config).map(|bind| format!("bind = {}", bind)));
+            }
         }
-    }
-    
-    // XDG Desktop Portal
-    if config.xdg_portal {
-        statements.push("# XDG Desktop Portal".to_string());
-        statements.push("exec-once = dbus-update-activation-environment --systemd WAYLAND_DISPLAY XDG_CURRENT_DESKTOP".to_string());
-        statements.push("exec-once = systemctl --user import-environment WAYLAND_DISPLAY XDG_CURRENT_DESKTOP".to_string());
-        statements.push("".to_string());
-    }
-    
-    // Authentication agent
-    if let Some(ref auth) = config.auth_agent {
-        statements.push("# Authentication agent".to_string());
-        let cmd = match auth.as_str() {
-            "hyprpolkitagent" => "exec-once = hyprpolkitagent",
-            "polkit-kde-agent" => "exec-once = /usr/lib/polkit-kde-authentication-agent-1",
-            "polkit-gnome" => "exec-once = /usr/lib/polkit-gnome/polkit-gnome-authentication-agent-1",
-            _ => "",
-        };
-        if !cmd.is_empty() {
-            statements.push(cmd.to_string());
-            statements.push("".to_string());
+        
+        if lines.is_empty() {
+            continue;
         }
-    }
-    
-    // Status bar
-    if let Some(ref bar) = config.status_bar {
-        statements.push("# Status bar".to_string());
-        let cmd = match bar.as_str() {
-            "waybar" => "exec-once = waybar",
-            "polybar" => "exec-once = polybar",
-            "eww" => "exec-once = eww daemon && eww open bar",
-            "ironbar" => "exec-once = ironbar",
-            _ => "",
+        
+        // A lone component may bring its own comment, e.g. "Audio system (PipeWire)"
+        let comment = match components.as_slice() {
+            [component] => component.config_comment.as_ref().or(step.config_comment.as_ref()),
+            _ => step.config_comment.as_ref(),
         };
-        if !cmd.is_empty() {
-            statements.push(cmd.to_string());
-            statements.push("".to_string());
-        }
-    }
-    
-    // Wallpaper utilities
-    if !config.wallpaper_utils.is_empty() {
-        statements.push("# Wallpaper utilities".to_string());
-        for wallpaper in &config.wallpaper_utils {
-            let cmd = match wallpaper.as_str() {
-                "hyprpaper" => "exec-once = hyprpaper",
-                "swww" => "exec-once = swww-daemon",
-                "swaybg" => "exec-once = swaybg -i /path/to/wallpaper.png  # Update path",
-                "mpvpaper" => "exec-once = mpvpaper '*' /path/to/video.mp4  # Update path",
-                "wpaperd" => "exec-once = wpaperd",
-                _ => "",
-            };
-            if !cmd.is_empty() {
-                statements.push(cmd.to_string());
-            }
-        }
+        statements.push(format!("# {}", comment.unwrap_or(&step.name)));
+        statements.extend(lines);
         statements.push("".to_string());
     }
     
-    // Clipboard manager
-    if let Some(ref clip) = config.clipboard_manager {
-        statements.push("# Clipboard manager".to_string());
-        let cmd = match clip.as_str() {
-            "cliphist" => "exec-once = wl-paste --type text --watch cliphist store",
-            "clipman" => "exec-once = wl-paste -t text --watch clipman store",
-            "clipse" => "exec-once = clipse -listen",
-            "copyq" => "exec-once = copyq",
-            "wl-clip-persist" => "exec-once = wl-clip-persist --clipboard both",
-            _ => "",
-        };
-        if !cmd.is_empty() {
-            statements.push(cmd.to_string());
-            statements.push("".to_string());
-        }
+    statements
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pacman::Package;
+    use runner::RecordingRunner;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
     }
-    
-    // UWSM
-    if config.uwsm {
-        statements.push("# UWSM is configured via systemd units".to_string());
-        statements.push("".to_string());
+
+    fn sync_db(names: &[&str]) -> PackageDb {
+        PackageDb::from_packages(names.iter().map(|name| Package { name: name.to_string(), ..Default::default() }))
+    }
+
+    /// The packages each successful pacman transaction installed.
+    fn installed(runner: &RecordingRunner, failures: &[&str]) -> Vec<String> {
+        let mut installed: Vec<String> = runner
+            .commands()
+            .iter()
+            .filter(|cmd| !cmd.args.iter().any(|arg| failures.contains(&arg.as_str())))
+            .flat_map(|cmd| cmd.args.iter().filter(|arg| !arg.starts_with('-')).cloned())
+            .collect();
+        installed.sort();
+        installed
+    }
+
+    fn install(packages: &[&str], known: &[&str], failures: &[&str]) -> (Result<(), String>, Vec<String>) {
+        // Dry-run options keep the tests from writing the default log file
+        set_options(Options { dry_run: true, ..Default::default() });
+        let runner = RecordingRunner::failing_on(failures);
+        let result = install_pacman_packages(&runner, &sync_db(known), &names(packages));
+        (result, installed(&runner, failures))
+    }
+
+    #[test]
+    fn install_succeeds_in_one_transaction() {
+        let (result, installed) = install(&["a", "b", "c"], &["a", "b", "c"], &[]);
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(installed, names(&["a", "b", "c"]));
+    }
+
+    #[test]
+    fn install_finds_one_bad_package_among_many() {
+        let all = ["a", "b", "c", "d", "e", "f", "g"];
+        let (result, installed) = install(&all, &all, &["e"]);
+
+        assert_eq!(result, Err("could not install e".to_string()));
+        assert_eq!(installed, names(&["a", "b", "c", "d", "f", "g"]));
+    }
+
+    #[test]
+    fn install_finds_two_bad_packages() {
+        let all = ["a", "b", "c", "d", "e", "f"];
+        let (result, installed) = install(&all, &all, &["b", "f"]);
+
+        assert_eq!(result, Err("could not install b, f".to_string()));
+        assert_eq!(installed, names(&["a", "c", "d", "e"]));
+    }
+
+    #[test]
+    fn install_sets_aside_unknown_names_and_finds_failing_known_ones() {
+        // "typo" makes the batch fail on its own, "broken" fails to install
+        let (result, installed) = install(&["a", "typo", "broken", "b"], &["a", "broken", "b"], &["typo", "broken"]);
+
+        assert_eq!(result, Err("could not install typo, broken".to_string()));
+        assert_eq!(installed, names(&["a", "b"]));
+    }
+
+    #[test]
+    fn install_reports_a_lone_failing_package_with_its_error() {
+        let (result, installed) = install(&["a"], &["a"], &["a"]);
+
+        assert_eq!(result, Err("could not install a".to_string()));
+        assert!(installed.is_empty());
     }
-    
-    statements
 }
@@ -0,0 +1,445 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::plan::{FileWrite, InstallPlan, SystemCommand};
+
+const MKINITCPIO_CONF: &str = "/etc/mkinitcpio.conf";
+
+/// Drop-in directory sourced after mkinitcpio.conf, in file name order.
+const DROP_IN_DIR: &str = "/etc/mkinitcpio.conf.d";
+
+/// Module options, included in the initramfs by the `modconf` hook.
+const MODPROBE_DIR: &str = "/etc/modprobe.d";
+
+/// The installer's own drop-in, named to sort after most others.
+const DROP_IN_NAME: &str = "90-hyprland-installer.conf";
+
+/// Hooks are added in front of the first of these, so they run before
+/// block devices are set up (where `kms` and `plymouth` belong).
+const HOOK_ANCHORS: [&str; 2] = ["block", "filesystems"];
+
+/// Entries the chosen components want in (or out of) the initramfs.
+#[derive(Debug, Default, Clone)]
+pub struct Changes {
+    pub add_modules: Vec<String>,
+    pub add_hooks: Vec<String>,
+    pub remove_hooks: Vec<String>,
+}
+
+impl Changes {
+    pub fn is_empty(&self) -> bool {
+        self.add_modules.is_empty() && self.add_hooks.is_empty() && self.remove_hooks.is_empty()
+    }
+}
+
+/// The MODULES and HOOKS arrays as mkinitcpio would see them.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Arrays {
+    pub modules: Vec<String>,
+    pub hooks: Vec<String>,
+}
+
+impl Arrays {
+    /// Applies the assignments in one config file (`NAME=(...)` replaces,
+    /// `NAME+=(...)` appends), like sourcing it would.
+    fn apply(&mut self, content: &str) {
+        for (name, append, values) in parse_assignments(content) {
+            let array = match name.as_str() {
+                "MODULES" => &mut self.modules,
+                "HOOKS" => &mut self.hooks,
+                _ => continue,
+            };
+            if !append {
+                array.clear();
+            }
+            array.extend(values);
+        }
+    }
+
+    /// The arrays after `changes`. Entries already present are left alone,
+    /// so applying the same changes twice gives the same result.
+    fn with_changes(&self, changes: &Changes) -> Arrays {
+        let mut result = self.clone();
+
+        for module in &changes.add_modules {
+            if !result.modules.contains(module) {
+                result.modules.push(module.clone());
+            }
+        }
+
+        result.hooks.retain(|hook| !changes.remove_hooks.contains(hook));
+        for hook in &changes.add_hooks {
+            if result.hooks.contains(hook) {
+                continue;
+            }
+            let anchor = HOOK_ANCHORS
+                .iter()
+                .find_map(|anchor| result.hooks.iter().position(|h| h == anchor));
+            match anchor {
+                Some(index) => result.hooks.insert(index, hook.clone()),
+                None => result.hooks.push(hook.clone()),
+            }
+        }
+
+        result
+    }
+}
+
+/// Splits a shell config into `(name, is_append, values)` for each array
+/// assignment. Arrays may span several lines; comments are ignored.
+fn parse_assignments(content: &str) -> Vec<(String, bool, Vec<String>)> {
+    let mut assignments = Vec::new();
+    let mut open: Option<(String, bool, Vec<String>)> = None;
+
+    for line in content.lines() {
+        let line = strip_comment(line);
+
+        let rest = match open {
+            Some(_) => line,
+            None => {
+                let (name, append, rest) = match split_assignment(line) {
+                    Some(parts) => parts,
+                    None => continue,
+                };
+                open = Some((name, append, Vec::new()));
+                rest
+            }
+        };
+
+        let (values, closed) = match rest.find(')') {
+            Some(end) => (&rest[..end], true),
+            None => (rest, false),
+        };
+        if let Some((_, _, ref mut array)) = open {
+            array.extend(values.split_whitespace().map(|v| v.trim_matches(['"', '\'']).to_string()));
+        }
+        if closed {
+            assignments.extend(open.take());
+        }
+    }
+
+    assignments
+}
+
+/// Splits `NAME=(rest` or `NAME+=(rest` into its parts.
+fn split_assignment(line: &str) -> Option<(String, bool, &str)> {
+    let (name, rest) = line.trim_start().split_once('=')?;
+    let rest = rest.strip_prefix('(')?;
+    let (name, append) = match name.strip_suffix('+') {
+        Some(name) => (name, true),
+        None => (name, false),
+    };
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return None;
+    }
+    Some((name.to_string(), append, rest))
+}
+
+fn strip_comment(line: &str) -> &str {
+    match line.find('#') {
+        Some(index) => &line[..index],
+        None => line,
+    }
+}
+
+/// Rewrites the `NAME=(...)` assignment in `content` (or `NAME+=(...)` with
+/// `append`), however many lines it spans, to hold `values`, or appends one
+/// if there is none.
+fn replace_array(content: &str, name: &str, values: &[String], append: bool) -> String {
+    let assignment = format!("{}{}=({})", name, if append { "+" } else { "" }, values.join(" "));
+    let mut lines: Vec<String> = Vec::new();
+    let mut replaced = false;
+    let mut skipping = false;
+
+    for line in content.lines() {
+        if skipping {
+            skipping = !strip_comment(line).contains(')');
+            continue;
+        }
+
+        let is_target = !replaced
+            && split_assignment(strip_comment(line)).is_some_and(|(n, a, _)| n == name && a == append);
+        if is_target {
+            lines.push(assignment.clone());
+            replaced = true;
+            skipping = !strip_comment(line).contains(')');
+        } else {
+            lines.push(line.to_string());
+        }
+    }
+
+    if !replaced {
+        lines.push(assignment);
+    }
+    lines.join("\n") + "\n"
+}
+
+/// Updates the installer's drop-in so array `name` goes from `before` to
+/// `after`. Entries that are only appended go into a `NAME+=(...)` line,
+/// which keeps later edits of mkinitcpio.conf in effect; only removing or
+/// placing entries needs the whole array.
+fn update_drop_in(content: &str, name: &str, before: &[String], after: &[String]) -> String {
+    match after.strip_prefix(before) {
+        Some([]) => content.to_string(),
+        Some(added) => {
+            let mut values: Vec<String> = parse_assignments(content)
+                .into_iter()
+                .filter(|(n, append, _)| n == name && *append)
+                .flat_map(|(_, _, values)| values)
+                .collect();
+            for value in added {
+                if !values.contains(value) {
+                    values.push(value.clone());
+                }
+            }
+            replace_array(content, name, &values, true)
+        }
+        None => replace_array(content, name, after, false),
+    }
+}
+
+/// Applies the change from `before` to `after` to `content`: the
+/// installer's drop-in with `drop_in`, otherwise mkinitcpio.conf itself,
+/// whose arrays are rewritten in place.
+fn edit_config(content: &str, before: &Arrays, after: &Arrays, drop_in: bool) -> String {
+    let mut content = content.to_string();
+    if drop_in {
+        content = update_drop_in(&content, "MODULES", &before.modules, &after.modules);
+        content = update_drop_in(&content, "HOOKS", &before.hooks, &after.hooks);
+    } else {
+        if after.modules != before.modules {
+            content = replace_array(&content, "MODULES", &after.modules, false);
+        }
+        if after.hooks != before.hooks {
+            content = replace_array(&content, "HOOKS", &after.hooks, false);
+        }
+    }
+    content
+}
+
+/// The drop-in files in `dir`, in the order mkinitcpio sources them.
+fn drop_ins(dir: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|ext| ext == "conf"))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    paths.sort();
+    paths
+}
+
+/// The effective MODULES and HOOKS of this system: mkinitcpio.conf followed
+/// by every drop-in.
+pub fn current_arrays() -> Option<Arrays> {
+    let mut arrays = Arrays::default();
+    arrays.apply(&fs::read_to_string(MKINITCPIO_CONF).ok()?);
+    for path in drop_ins(Path::new(DROP_IN_DIR)) {
+        if let Ok(content) = fs::read_to_string(&path) {
+            arrays.apply(&content);
+        }
+    }
+    Some(arrays)
+}
+
+/// Describes what changes between `before` and `after`, e.g.
+/// "MODULES +amdgpu, HOOKS -kms".
+fn describe(before: &Arrays, after: &Arrays) -> String {
+    let diff = |name: &str, old: &[String], new: &[String]| -> Option<String> {
+        let added = new.iter().filter(|v| !old.contains(v)).map(|v| format!("+{}", v));
+        let removed = old.iter().filter(|v| !new.contains(v)).map(|v| format!("-{}", v));
+        let changes: Vec<String> = added.chain(removed).collect();
+        (!changes.is_empty()).then(|| format!("{} {}", name, changes.join(" ")))
+    };
+
+    [diff("MODULES", &before.modules, &after.modules), diff("HOOKS", &before.hooks, &after.hooks)]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Plans the mkinitcpio edits for `changes` and the `mkinitcpio -P` that
+/// applies them. Systems with a drop-in directory get the installer's own
+/// drop-in; otherwise mkinitcpio.conf is edited in place, after a backup.
+pub fn plan_changes(plan: &mut InstallPlan, changes: &Changes) {
+    if changes.is_empty() {
+        return;
+    }
+
+    let before = match current_arrays() {
+        Some(arrays) => arrays,
+        None => {
+            println!("ℹ️  No {} found, skipping initramfs changes", MKINITCPIO_CONF);
+            return;
+        }
+    };
+
+    let after = before.with_changes(changes);
+    if after == before {
+        return;
+    }
+
+    let drop_in_dir = Path::new(DROP_IN_DIR);
+    let drop_in = drop_in_dir.is_dir();
+    let (path, current) = if drop_in {
+        let path = drop_in_dir.join(DROP_IN_NAME);
+        let current = fs::read_to_string(&path).unwrap_or_else(|_| "# Managed by hyprland-installer\n".to_string());
+        (path, current)
+    } else {
+        let path = PathBuf::from(MKINITCPIO_CONF);
+        let current = fs::read_to_string(&path).unwrap_or_default();
+        (path, current)
+    };
+    let content = edit_config(&current, &before, &after, drop_in);
+
+    plan.file_writes.push(FileWrite {
+        backup: path.exists().then(|| path.with_extension("conf.backup")),
+        path,
+        content,
+        owner: None,
+        description: format!("mkinitcpio: {}", describe(&before, &after)),
+        note: None,
+    });
+    regenerate(plan);
+}
+
+/// The command that rebuilds every initramfs and UKI.
+pub fn regenerate_command() -> SystemCommand {
+    SystemCommand {
+        argv: vec!["mkinitcpio".to_string(), "-P".to_string()],
+        description: "Regenerating the initramfs".to_string(),
+    }
+}
+
+/// Adds `mkinitcpio -P` to the plan, once.
+pub fn regenerate(plan: &mut InstallPlan) {
+    if plan.system_commands.iter().any(|c| c.argv.first().is_some_and(|p| p == "mkinitcpio")) {
+        return;
+    }
+    plan.system_commands.push(regenerate_command());
+}
+
+/// Whether `path` is a config that goes into the initramfs: mkinitcpio.conf,
+/// a drop-in, or module options from modprobe.d.
+pub fn affects_initramfs(path: &Path) -> bool {
+    path == Path::new(MKINITCPIO_CONF) || path.starts_with(DROP_IN_DIR) || path.starts_with(MODPROBE_DIR)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    fn arrays(content: &str) -> Arrays {
+        let mut arrays = Arrays::default();
+        arrays.apply(content);
+        arrays
+    }
+
+    const CONF: &str = "\
+# MODULES=(example)
+MODULES=()
+BINARIES=()
+#HOOKS=(base udev kms)
+HOOKS=(base udev autodetect microcode modconf kms keyboard  # the defaults
+    keymap consolefont block filesystems fsck)
+COMPRESSION=\"zstd\"
+";
+
+    fn nvidia() -> Changes {
+        Changes {
+            add_modules: strings(&["nvidia", "nvidia_drm"]),
+            remove_hooks: strings(&["kms"]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parses_multi_line_arrays_with_comments() {
+        let assignments = parse_assignments(CONF);
+        assert_eq!(assignments.len(), 3);
+        assert_eq!(assignments[0], ("MODULES".to_string(), false, Vec::new()));
+        assert_eq!(
+            assignments[2].2,
+            strings(&["base", "udev", "autodetect", "microcode", "modconf", "kms", "keyboard", "keymap", "consolefont", "block", "filesystems", "fsck"])
+        );
+    }
+
+    #[test]
+    fn append_lines_add_to_the_array() {
+        let arrays = arrays("MODULES=(i915)\nMODULES+=(nvidia \"nvidia_drm\") # trailing\nHOOKS=(base)\nHOOKS=(base udev)\n");
+        assert_eq!(arrays.modules, strings(&["i915", "nvidia", "nvidia_drm"]));
+        assert_eq!(arrays.hooks, strings(&["base", "udev"]));
+    }
+
+    #[test]
+    fn commented_out_assignments_are_ignored() {
+        let arrays = arrays("#HOOKS=(base kms)\n# MODULES=(nvidia)\nHOOKS=(base udev)\n");
+        assert!(arrays.modules.is_empty());
+        assert_eq!(arrays.hooks, strings(&["base", "udev"]));
+    }
+
+    #[test]
+    fn hooks_go_before_block_or_filesystems() {
+        let add = Changes { add_hooks: strings(&["plymouth"]), ..Default::default() };
+
+        let with_block = arrays("HOOKS=(base udev block filesystems)").with_changes(&add);
+        assert_eq!(with_block.hooks, strings(&["base", "udev", "plymouth", "block", "filesystems"]));
+
+        let without_block = arrays("HOOKS=(base udev filesystems fsck)").with_changes(&add);
+        assert_eq!(without_block.hooks, strings(&["base", "udev", "plymouth", "filesystems", "fsck"]));
+
+        let neither = arrays("HOOKS=(base udev)").with_changes(&add);
+        assert_eq!(neither.hooks, strings(&["base", "udev", "plymouth"]));
+    }
+
+    #[test]
+    fn replace_array_rewrites_a_multi_line_assignment() {
+        let updated = replace_array(CONF, "HOOKS", &strings(&["base", "udev", "block"]), false);
+        assert_eq!(
+            updated,
+            "# MODULES=(example)\nMODULES=()\nBINARIES=()\n#HOOKS=(base udev kms)\nHOOKS=(base udev block)\nCOMPRESSION=\"zstd\"\n"
+        );
+    }
+
+    #[test]
+    fn editing_the_main_config_is_idempotent() {
+        let before = arrays(CONF);
+        let after = before.with_changes(&nvidia());
+        assert!(!after.hooks.contains(&"kms".to_string()));
+
+        let edited = edit_config(CONF, &before, &after, false);
+        assert_eq!(arrays(&edited), after);
+
+        let again = arrays(&edited).with_changes(&nvidia());
+        assert_eq!(again, after);
+        assert_eq!(edit_config(&edited, &after, &again, false), edited);
+    }
+
+    #[test]
+    fn drop_in_appends_modules_and_writes_hooks_only_to_remove_one() {
+        let base = arrays(CONF);
+
+        let modules_only = Changes { add_modules: strings(&["nvidia"]), ..Default::default() };
+        let after = base.with_changes(&modules_only);
+        let drop_in = edit_config("# Managed by hyprland-installer\n", &base, &after, true);
+        assert_eq!(drop_in, "# Managed by hyprland-installer\nMODULES+=(nvidia)\n");
+
+        let after = base.with_changes(&nvidia());
+        let drop_in = edit_config(&drop_in, &arrays(&format!("{}{}", CONF, drop_in)), &after, true);
+        assert!(drop_in.contains("MODULES+=(nvidia nvidia_drm)\n"));
+        assert!(drop_in.contains("HOOKS=(base udev autodetect microcode modconf keyboard keymap consolefont block filesystems fsck)\n"));
+
+        // Sourced after mkinitcpio.conf, the drop-in gives the wanted arrays, and a second run changes nothing
+        let effective = arrays(&format!("{}{}", CONF, drop_in));
+        assert_eq!(effective, after);
+        assert_eq!(effective.with_changes(&nvidia()), effective);
+        assert_eq!(edit_config(&drop_in, &effective, &effective, true), drop_in);
+    }
+}
@@ -0,0 +1,142 @@
+use std::fs;
+use std::path::Path;
+
+use crate::gpu::{Gpu, GpuVendor};
+use crate::mkinitcpio;
+use crate::plan::{FileWrite, InstallPlan};
+
+/// Each installed kernel leaves a `pkgbase` file in its module directory.
+const KERNEL_MODULES_DIR: &str = "/usr/lib/modules";
+
+/// Options for the NVIDIA DRM module. Hyprland needs kernel modesetting,
+/// and `fbdev=1` gives a proper framebuffer console.
+const MODPROBE_CONF: &str = "/etc/modprobe.d/nvidia.conf";
+const MODPROBE_OPTIONS: &str = "options nvidia_drm modeset=1 fbdev=1\n";
+
+/// First PCI device id of the Turing generation. Turing and newer cards
+/// should use the open kernel modules.
+const FIRST_TURING_DEVICE: u16 = 0x1e00;
+
+/// The kernel packages installed on this system, e.g. `linux`, `linux-lts`.
+pub fn installed_kernels() -> Vec<String> {
+    let entries = match fs::read_dir(KERNEL_MODULES_DIR) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut kernels: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| fs::read_to_string(e.path().join("pkgbase")).ok())
+        .map(|pkgbase| pkgbase.trim().to_string())
+        .filter(|pkgbase| !pkgbase.is_empty())
+        .collect();
+    kernels.sort();
+    kernels.dedup();
+    kernels
+}
+
+/// Picks the kernel module package for the NVIDIA cards in `gpus`. The
+/// prebuilt modules only match the stock `linux` kernel; any other kernel
+/// needs the DKMS flavour plus that kernel's headers.
+pub fn driver_packages(gpus: &[Gpu], kernels: &[String]) -> Vec<String> {
+    let open = gpus
+        .iter()
+        .filter(|gpu| gpu.vendor == GpuVendor::Nvidia)
+        .any(|gpu| gpu.device_id.is_some_and(|id| id >= FIRST_TURING_DEVICE));
+    let stock_only = kernels.is_empty() || kernels.iter().all(|k| k == "linux");
+
+    let mut packages = vec![match (open, stock_only) {
+        (true, true) => "nvidia-open",
+        (true, false) => "nvidia-open-dkms",
+        (false, true) => "nvidia",
+        (false, false) => "nvidia-dkms",
+    }
+    .to_string()];
+
+    if !stock_only {
+        packages.extend(kernels.iter().map(|k| format!("{}-headers", k)));
+    }
+    packages
+}
+
+/// Adds the NVIDIA driver and modesetting options to `plan`. The early KMS
+/// modules come from the catalog like any other initramfs change.
+pub fn plan_setup(plan: &mut InstallPlan, gpus: &[Gpu]) {
+    let kernels = installed_kernels();
+    if !kernels.is_empty() {
+        plan.notes.push(format!("NVIDIA driver chosen for kernels: {}", kernels.join(", ")));
+    }
+
+    for package in driver_packages(gpus, &kernels) {
+        if !plan.repo_packages.contains(&package) {
+            plan.repo_packages.push(package);
+        }
+    }
+
+    let current = fs::read_to_string(MODPROBE_CONF).ok();
+    if current.as_deref() != Some(MODPROBE_OPTIONS) {
+        plan.file_writes.push(FileWrite {
+            path: MODPROBE_CONF.into(),
+            content: MODPROBE_OPTIONS.to_string(),
+            backup: current.map(|_| Path::new(MODPROBE_CONF).with_extension("conf.backup")),
+            owner: None,
+            description: "NVIDIA modesetting options".to_string(),
+            note: None,
+        });
+
+        // The modconf hook copies modprobe options into the initramfs
+        mkinitcpio::regenerate(plan);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RTX 3070 (Ampere) and GTX 1080 (Pascal)
+    const TURING_OR_NEWER: u16 = 0x2484;
+    const PRE_TURING: u16 = 0x1b80;
+
+    fn nvidia(device_id: u16) -> Gpu {
+        Gpu {
+            address: "0000:01:00.0".to_string(),
+            vendor: GpuVendor::Nvidia,
+            vendor_id: 0x10de,
+            device_id: Some(device_id),
+        }
+    }
+
+    fn kernels(names: &[&str]) -> Vec<String> {
+        names.iter().map(|k| k.to_string()).collect()
+    }
+
+    #[test]
+    fn turing_on_stock_kernel_uses_open_modules() {
+        assert_eq!(driver_packages(&[nvidia(TURING_OR_NEWER)], &kernels(&["linux"])), vec!["nvidia-open"]);
+    }
+
+    #[test]
+    fn pre_turing_on_stock_kernel_uses_closed_modules() {
+        assert_eq!(driver_packages(&[nvidia(PRE_TURING)], &kernels(&["linux"])), vec!["nvidia"]);
+    }
+
+    #[test]
+    fn extra_kernel_needs_dkms_and_every_kernels_headers() {
+        let kernels = kernels(&["linux", "linux-lts"]);
+
+        assert_eq!(
+            driver_packages(&[nvidia(TURING_OR_NEWER)], &kernels),
+            vec!["nvidia-open-dkms", "linux-headers", "linux-lts-headers"]
+        );
+        assert_eq!(
+            driver_packages(&[nvidia(PRE_TURING)], &kernels),
+            vec!["nvidia-dkms", "linux-headers", "linux-lts-headers"]
+        );
+    }
+
+    #[test]
+    fn no_detected_kernels_counts_as_stock() {
+        assert_eq!(driver_packages(&[nvidia(TURING_OR_NEWER)], &[]), vec!["nvidia-open"]);
+        assert_eq!(driver_packages(&[nvidia(PRE_TURING)], &[]), vec!["nvidia"]);
+    }
+}
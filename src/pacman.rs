@@ -0,0 +1,302 @@
+use flate2::read::GzDecoder;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::runner::{runner, Cmd};
+
+/// Where pacman keeps the downloaded repository databases.
+const SYNC_DB_DIR: &str = "/var/lib/pacman/sync";
+
+/// Where pacman records the installed packages, one directory each.
+const LOCAL_DB_DIR: &str = "/var/lib/pacman/local";
+
+/// AUR RPC endpoint used to check which names exist in the AUR.
+const AUR_INFO_URL: &str = "https://aur.archlinux.org/rpc/v5/info";
+
+/// A package entry from a repository, local or AUR database.
+#[derive(Debug, Clone, Default)]
+pub struct Package {
+    pub name: String,
+    /// Repository the package comes from, e.g. `extra` (`aur` for the AUR,
+    /// empty for installed packages)
+    pub repo: String,
+    /// One-line summary of the package
+    pub description: String,
+    /// Names this package can stand in for, without version constraints
+    pub provides: Vec<String>,
+    /// Names this package cannot be installed alongside
+    pub conflicts: Vec<String>,
+    /// Names this package needs installed, without version constraints
+    pub depends: Vec<String>,
+}
+
+impl Package {
+    /// Whether this package is, or provides, `name`.
+    pub fn satisfies(&self, name: &str) -> bool {
+        self.name == name || self.provides.iter().any(|p| p == name)
+    }
+
+    /// Whether this package and `other` cannot be installed together,
+    /// judged from the CONFLICTS entries of both.
+    pub fn conflicts_with(&self, other: &Package) -> bool {
+        self.name != other.name
+            && (self.conflicts.iter().any(|c| other.satisfies(c)) || other.conflicts.iter().any(|c| self.satisfies(c)))
+    }
+}
+
+/// A set of packages by name: either every package in the official
+/// repositories (read from the `*.db` files pacman downloads with
+/// `pacman -Sy`) or every installed package.
+#[derive(Debug, Default)]
+pub struct PackageDb {
+    packages: HashMap<String, Package>,
+}
+
+impl PackageDb {
+    /// Reads every `*.db` file in `dir`. A database that cannot be read is
+    /// reported and left out; a missing directory gives an empty database.
+    pub fn load_sync(dir: &Path) -> Self {
+        let mut db = PackageDb::default();
+
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return db,
+        };
+
+        let mut paths: Vec<_> = entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|ext| ext == "db"))
+            .collect();
+        paths.sort();
+
+        for path in paths {
+            let repo = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+            if let Err(e) = db.read_repo(&path, &repo) {
+                eprintln!("⚠️  Could not read {}: {}", path.display(), e);
+            }
+        }
+
+        db
+    }
+
+    /// Adds the packages from one repository database (a tar archive,
+    /// usually gzip-compressed, with a `desc` file per package).
+    fn read_repo(&mut self, path: &Path, repo: &str) -> Result<(), String> {
+        let mut reader = BufReader::new(File::open(path).map_err(|e| e.to_string())?);
+
+        let mut magic = [0u8; 2];
+        reader.read_exact(&mut magic).map_err(|e| e.to_string())?;
+        let reader = std::io::Cursor::new(magic).chain(reader);
+
+        let archive: Box<dyn Read> = if magic == [0x1f, 0x8b] {
+            Box::new(GzDecoder::new(reader))
+        } else {
+            Box::new(reader)
+        };
+
+        let mut archive = tar::Archive::new(archive);
+        for entry in archive.entries().map_err(|e| e.to_string())? {
+            let mut entry = entry.map_err(|e| e.to_string())?;
+            let is_desc = entry.path().map(|p| p.ends_with("desc")).unwrap_or(false);
+            if !is_desc {
+                continue;
+            }
+
+            let mut content = String::new();
+            entry.read_to_string(&mut content).map_err(|e| e.to_string())?;
+            let package = package_from_desc(&content, repo);
+
+            // Earlier repositories take precedence, as in pacman.conf
+            if !package.name.is_empty() && !self.packages.contains_key(&package.name) {
+                self.packages.insert(package.name.clone(), package);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.packages.is_empty()
+    }
+
+    /// Reads the local database: a directory per installed package, each
+    /// with a `desc` file.
+    pub fn load_local(dir: &Path) -> Self {
+        let mut db = PackageDb::default();
+
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return db,
+        };
+
+        for entry in entries.filter_map(|e| e.ok()) {
+            if let Ok(content) = fs::read_to_string(entry.path().join("desc")) {
+                let package = package_from_desc(&content, "");
+                if !package.name.is_empty() {
+                    db.packages.insert(package.name.clone(), package);
+                }
+            }
+        }
+
+        db
+    }
+
+    pub fn packages(&self) -> impl Iterator<Item = &Package> {
+        self.packages.values()
+    }
+
+    /// Looks up a package by its exact name.
+    pub fn get(&self, name: &str) -> Option<&Package> {
+        self.packages.get(name)
+    }
+
+    /// The packages that depend on `name`, either directly or through
+    /// something it provides.
+    pub fn required_by(&self, name: &str) -> Vec<&Package> {
+        let package = match self.packages.get(name) {
+            Some(package) => package,
+            None => return Vec::new(),
+        };
+        let mut dependents: Vec<&Package> = self
+            .packages
+            .values()
+            .filter(|p| p.name != name && p.depends.iter().any(|d| package.satisfies(d)))
+            .collect();
+        dependents.sort_by(|a, b| a.name.cmp(&b.name));
+        dependents
+    }
+
+    /// Looks up a package by name, falling back to a package that provides it
+    /// (pacman resolves `pacman -S <name>` the same way).
+    pub fn find(&self, name: &str) -> Option<&Package> {
+        self.packages.get(name).or_else(|| {
+            let mut providers: Vec<&Package> = self
+                .packages
+                .values()
+                .filter(|p| p.provides.iter().any(|provided| provided == name))
+                .collect();
+            providers.sort_by(|a, b| a.name.cmp(&b.name));
+            providers.into_iter().next()
+        })
+    }
+
+    /// A database holding just `packages`.
+    #[cfg(test)]
+    pub fn from_packages(packages: impl IntoIterator<Item = Package>) -> Self {
+        PackageDb {
+            packages: packages.into_iter().map(|p| (p.name.clone(), p)).collect(),
+        }
+    }
+}
+
+/// Parses a pacman `desc` file: `%SECTION%` headers followed by one value
+/// per line, with a blank line between sections.
+pub fn parse_desc(content: &str) -> HashMap<String, Vec<String>> {
+    let mut sections: HashMap<String, Vec<String>> = HashMap::new();
+    let mut current: Option<String> = None;
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.len() > 2 && line.starts_with('%') && line.ends_with('%') {
+            let name = line.trim_matches('%').to_string();
+            sections.entry(name.clone()).or_default();
+            current = Some(name);
+        } else if line.is_empty() {
+            current = None;
+        } else if let Some(ref name) = current {
+            sections.entry(name.clone()).or_default().push(line.to_string());
+        }
+    }
+
+    sections
+}
+
+/// Strips a version constraint such as `=1.2` or `>=3` from a dependency.
+pub fn dependency_name(dependency: &str) -> &str {
+    dependency.split(['=', '<', '>']).next().unwrap_or(dependency)
+}
+
+/// Package names from a list of dependencies, without version constraints.
+fn names(dependencies: Option<&Vec<String>>) -> Vec<String> {
+    dependencies
+        .map(|v| v.iter().map(|d| dependency_name(d).to_string()).collect())
+        .unwrap_or_default()
+}
+
+fn package_from_desc(content: &str, repo: &str) -> Package {
+    let sections = parse_desc(content);
+    let first = |key: &str| sections.get(key).and_then(|v| v.first()).cloned().unwrap_or_default();
+
+    Package {
+        name: first("NAME"),
+        repo: repo.to_string(),
+        description: first("DESC"),
+        provides: names(sections.get("PROVIDES")),
+        conflicts: names(sections.get("CONFLICTS")),
+        depends: names(sections.get("DEPENDS")),
+    }
+}
+
+static SYNC_DB: OnceLock<PackageDb> = OnceLock::new();
+static LOCAL_DB: OnceLock<PackageDb> = OnceLock::new();
+
+/// The sync database of this system, read on first use.
+pub fn sync_db() -> &'static PackageDb {
+    SYNC_DB.get_or_init(|| PackageDb::load_sync(Path::new(SYNC_DB_DIR)))
+}
+
+/// The installed packages of this system, read on first use.
+pub fn local_db() -> &'static PackageDb {
+    LOCAL_DB.get_or_init(|| PackageDb::load_local(Path::new(LOCAL_DB_DIR)))
+}
+
+/// Looks `names` up in the AUR and returns the packages it has. Returns
+/// `None` when the AUR could not be reached, so callers can tell
+/// "not in the AUR" from "unknown".
+pub fn aur_info(names: &[String]) -> Option<Vec<Package>> {
+    if names.is_empty() {
+        return Some(Vec::new());
+    }
+
+    let query: Vec<String> = names.iter().map(|n| format!("arg%5B%5D={}", n)).collect();
+    let url = format!("{}?{}", AUR_INFO_URL, query.join("&"));
+
+    let output = runner()
+        .query(&Cmd::new("curl").args(["-sf", "--max-time", "15"]).arg(url))
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let response: serde_json::Value = serde_json::from_slice(&output.stdout).ok()?;
+    let results = response.get("results")?.as_array()?;
+
+    let list = |result: &serde_json::Value, key: &str| -> Vec<String> {
+        result
+            .get(key)
+            .and_then(|v| v.as_array())
+            .map(|v| v.iter().filter_map(|d| d.as_str()).map(|d| dependency_name(d).to_string()).collect())
+            .unwrap_or_default()
+    };
+
+    Some(
+        results
+            .iter()
+            .filter_map(|r| {
+                let name = r.get("Name")?.as_str()?.to_string();
+                Some(Package {
+                    name,
+                    repo: "aur".to_string(),
+                    description: r.get("Description").and_then(|d| d.as_str()).unwrap_or_default().to_string(),
+                    provides: list(r, "Provides"),
+                    conflicts: list(r, "Conflicts"),
+                    depends: list(r, "Depends"),
+                })
+            })
+            .collect(),
+    )
+}
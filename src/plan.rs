@@ -0,0 +1,461 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::catalog::catalog;
+use crate::cli::options;
+use crate::{bootloader, gpu, mkinitcpio, nvidia};
+use crate::pacman::{self, Package};
+use crate::{InstallConfig, MARKER_END, MARKER_START};
+
+/// Everything an installation will do, worked out up front from an
+/// `InstallConfig`. Dry runs print it; real runs hand it to `perform_installation`.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct InstallPlan {
+    /// AUR helper to build from source before anything else
+    pub aur_helper: Option<String>,
+    pub repo_packages: Vec<String>,
+    pub aur_packages: Vec<String>,
+    /// systemd units to enable, e.g. `sddm.service`
+    pub services: Vec<String>,
+    /// Display manager that owns `display-manager.service` right now
+    #[serde(default)]
+    pub active_display_manager: Option<String>,
+    /// systemd units to disable before enabling `services`, e.g. a display
+    /// manager that would fight the chosen greeter
+    #[serde(default)]
+    pub disable_services: Vec<String>,
+    pub user_commands: Vec<UserCommand>,
+    pub file_writes: Vec<FileWrite>,
+    /// Commands run as root once the files are written, e.g. `mkinitcpio -P`
+    #[serde(default)]
+    pub system_commands: Vec<SystemCommand>,
+    /// Follow-up hints from the chosen components
+    pub notes: Vec<String>,
+    /// Packages found neither in the official repositories nor in the AUR.
+    /// They are left out of the install.
+    #[serde(default)]
+    pub missing_packages: Vec<String>,
+    /// Installed packages that conflict with the new ones and are removed first
+    #[serde(default)]
+    pub replacements: Vec<Replacement>,
+}
+
+/// An installed package that has to make way for one from the plan.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Replacement {
+    pub installed: String,
+    pub replaced_by: String,
+}
+
+/// A command run as the regular (non-root) user.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserCommand {
+    pub user: String,
+    pub argv: Vec<String>,
+    pub description: String,
+}
+
+/// A command run as root after the files are written.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SystemCommand {
+    pub argv: Vec<String>,
+    pub description: String,
+}
+
+/// A file the installer writes, with its complete new content.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileWrite {
+    pub path: PathBuf,
+    pub content: String,
+    /// Where the current file is copied before it is overwritten
+    pub backup: Option<PathBuf>,
+    /// User that should own the file afterwards (set when running as root)
+    pub owner: Option<String>,
+    pub description: String,
+    /// Shown once the file has been written
+    pub note: Option<String>,
+}
+
+impl InstallPlan {
+    /// Works out the packages, services, commands and files for `config`.
+    /// The hyprland.conf update is only included when `update_config` is set.
+    pub fn from_config(config: &InstallConfig, update_config: bool) -> Self {
+        let mut plan = InstallPlan {
+            aur_helper: config.aur_helper.clone(),
+            ..Default::default()
+        };
+
+        let catalog = catalog();
+        let mut username = None;
+        let mut initramfs = mkinitcpio::Changes::default();
+        let mut kernel_params = bootloader::ParamChanges::default();
+
+        // Collect packages, services and commands from each chosen component
+        for step in &catalog.steps {
+            for id in config.selection(&step.key) {
+                let component = match catalog.component(&step.key, &id) {
+                    Some(component) => component,
+                    None => continue,
+                };
+
+                extend_unique(&mut plan.repo_packages, &component.packages);
+                extend_unique(&mut plan.aur_packages, &component.aur_packages);
+                extend_unique(&mut plan.services, &component.services);
+                extend_unique(&mut initramfs.add_modules, &component.mkinitcpio_modules);
+                extend_unique(&mut initramfs.add_hooks, &component.mkinitcpio_hooks);
+                extend_unique(&mut initramfs.remove_hooks, &component.mkinitcpio_remove_hooks);
+                extend_unique(&mut kernel_params.add, &component.kernel_params);
+                extend_unique(&mut kernel_params.remove, &component.remove_kernel_params);
+
+                // Commands such as xdg-user-dirs-update run as the regular user
+                for command in &component.user_commands {
+                    if username.is_none() {
+                        username = crate::get_username();
+                    }
+                    let command = match crate::expand_placeholders(command, config) {
+                        Some(command) => command,
+                        None => continue,
+                    };
+                    if let Some(ref user) = username {
+                        plan.user_commands.push(UserCommand {
+                            user: user.clone(),
+                            argv: command.split_whitespace().map(str::to_string).collect(),
+                            description: step.description.clone().unwrap_or_else(|| component.display_label()),
+                        });
+                    }
+                }
+
+                if let Some(ref notes) = component.notes {
+                    plan.notes.push(notes.clone());
+                }
+            }
+        }
+
+        mkinitcpio::plan_changes(&mut plan, &initramfs);
+        bootloader::plan_changes(&mut plan, &kernel_params);
+
+        if config.gpu_driver.as_deref() == Some("nvidia") {
+            nvidia::plan_setup(&mut plan, &gpu::detect_gpus(&options().sysfs_root));
+        }
+
+        plan.check_display_manager(config);
+
+        let aur_info = plan.classify_packages();
+        plan.find_conflicts(&aur_info);
+
+        if update_config {
+            if let Some(write) = hyprland_config_write(config) {
+                plan.file_writes.push(write);
+            }
+        }
+
+        plan
+    }
+
+    /// Looks up the display manager that is enabled now. If it is not the
+    /// chosen greeter, it has to be disabled first.
+    fn check_display_manager(&mut self, config: &InstallConfig) {
+        self.active_display_manager = crate::active_display_manager();
+
+        let chosen = match self.greeter_service(config) {
+            Some(service) => service,
+            None => return,
+        };
+
+        if let Some(ref active) = self.active_display_manager {
+            if active != &chosen {
+                self.disable_services.push(active.clone());
+            }
+        }
+    }
+
+    /// The service of the greeter chosen in `config`, e.g. `sddm.service`.
+    pub fn greeter_service(&self, config: &InstallConfig) -> Option<String> {
+        let greeter = config.greeter.as_ref()?;
+        catalog().component("greeter", greeter)?.services.first().cloned()
+    }
+
+    /// Keeps the active display manager, for when the user declines to
+    /// disable it: the chosen greeter is then not enabled either.
+    pub fn keep_display_manager(&mut self, config: &InstallConfig) {
+        if let Some(chosen) = self.greeter_service(config) {
+            self.services.retain(|s| s != &chosen);
+        }
+        self.disable_services.clear();
+    }
+
+    /// Routes each package to pacman or the AUR helper by looking it up in
+    /// the sync database. Without a sync database the catalog's split is kept.
+    /// Returns what the AUR knows about the AUR packages.
+    fn classify_packages(&mut self) -> Vec<Package> {
+        let db = pacman::sync_db();
+        if db.is_empty() {
+            println!("ℹ️  No pacman sync database found, using the catalog's repo/AUR split");
+            return pacman::aur_info(&self.aur_packages).unwrap_or_default();
+        }
+
+        let mut wanted = std::mem::take(&mut self.repo_packages);
+        extend_unique(&mut wanted, &std::mem::take(&mut self.aur_packages));
+
+        for name in wanted {
+            if db.find(&name).is_some() {
+                self.repo_packages.push(name);
+            } else {
+                self.aur_packages.push(name);
+            }
+        }
+
+        match pacman::aur_info(&self.aur_packages) {
+            Some(found) => {
+                let (aur, missing) = self
+                    .aur_packages
+                    .drain(..)
+                    .partition(|name| found.iter().any(|p| &p.name == name));
+                self.aur_packages = aur;
+                self.missing_packages = missing;
+                found
+            }
+            None => {
+                eprintln!("⚠️  Could not reach the AUR, assuming these packages are there: {}", self.aur_packages.join(" "));
+                Vec::new()
+            }
+        }
+    }
+
+    /// Finds installed packages that conflict with packages in the plan,
+    /// using the CONFLICTS/PROVIDES data of the sync, local and AUR databases.
+    fn find_conflicts(&mut self, aur_info: &[Package]) {
+        let sync = pacman::sync_db();
+        let local = pacman::local_db();
+
+        let new_packages: Vec<&Package> = self
+            .repo_packages
+            .iter()
+            .filter_map(|name| sync.find(name))
+            .chain(aur_info.iter())
+            .collect();
+
+        for new in new_packages {
+            // Reinstalling or upgrading a package is not a conflict
+            if local.find(&new.name).is_some_and(|installed| installed.name == new.name) {
+                continue;
+            }
+
+            let mut conflicting: Vec<&Package> = local.packages().filter(|installed| new.conflicts_with(installed)).collect();
+            conflicting.sort_by(|a, b| a.name.cmp(&b.name));
+
+            for installed in conflicting {
+                if !self.replacements.iter().any(|r| r.installed == installed.name) {
+                    self.replacements.push(Replacement {
+                        installed: installed.name.clone(),
+                        replaced_by: new.name.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Leaves out the packages that would replace installed ones, for when
+    /// the user declines the replacement.
+    pub fn drop_replacements(&mut self) {
+        for replacement in std::mem::take(&mut self.replacements) {
+            self.repo_packages.retain(|p| p != &replacement.replaced_by);
+            self.aur_packages.retain(|p| p != &replacement.replaced_by);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.aur_helper.is_none()
+            && self.repo_packages.is_empty()
+            && self.aur_packages.is_empty()
+            && self.services.is_empty()
+            && self.disable_services.is_empty()
+            && self.user_commands.is_empty()
+            && self.file_writes.is_empty()
+            && self.system_commands.is_empty()
+    }
+
+    pub fn print(&self) {
+        if self.is_empty() {
+            println!("ℹ️  Nothing to do");
+            return;
+        }
+
+        if let Some(ref helper) = self.aur_helper {
+            println!(" AUR helper to install: {}", helper);
+        }
+
+        if !self.repo_packages.is_empty() {
+            println!("\n Packages from official repositories:");
+            let db = pacman::sync_db();
+            for pkg in &self.repo_packages {
+                match db.find(pkg) {
+                    Some(found) => println!("   - {} ({})", pkg, found.repo),
+                    None => println!("   - {}", pkg),
+                }
+            }
+        }
+
+        if !self.aur_packages.is_empty() {
+            println!("\n Packages from AUR:");
+            for pkg in &self.aur_packages {
+                println!("   - {}", pkg);
+            }
+        }
+
+        if !self.replacements.is_empty() {
+            println!("\n Installed packages to replace:");
+            for replacement in &self.replacements {
+                println!("   - {} → {} (conflict)", replacement.installed, replacement.replaced_by);
+            }
+        }
+
+        if !self.missing_packages.is_empty() {
+            println!("\n ⚠️  Not found in the official repositories or the AUR (left out):");
+            for pkg in &self.missing_packages {
+                println!("   - {}", pkg);
+            }
+        }
+
+        if let Some(ref active) = self.active_display_manager {
+            println!("\n Active display manager: {}", active);
+        }
+
+        if !self.disable_services.is_empty() {
+            println!("\n Services to disable:");
+            for service in &self.disable_services {
+                println!("   - {}", service);
+            }
+        }
+
+        if !self.services.is_empty() {
+            println!("\n Services to enable:");
+            for service in &self.services {
+                println!("   - {}", service);
+            }
+        }
+
+        if !self.user_commands.is_empty() {
+            println!("\n Commands to run:");
+            for cmd in &self.user_commands {
+                println!("   - [{}] {}  # {}", cmd.user, cmd.argv.join(" "), cmd.description);
+            }
+        }
+
+        if !self.notes.is_empty() {
+            println!("\n Notes:");
+            for note in &self.notes {
+                println!("   - {}", note);
+            }
+        }
+
+        if !self.file_writes.is_empty() {
+            println!("\n Files to write:");
+            for write in &self.file_writes {
+                println!("   - {}  # {}", write.path.display(), write.description);
+                if let Some(ref backup) = write.backup {
+                    println!("     (backup: {})", backup.display());
+                }
+            }
+        }
+
+        if !self.system_commands.is_empty() {
+            println!("\n Commands to run afterwards:");
+            for cmd in &self.system_commands {
+                println!("   - {}  # {}", cmd.argv.join(" "), cmd.description);
+            }
+        }
+    }
+
+    /// Writes the plan as pretty-printed JSON so it can be reviewed or diffed.
+    pub fn save_json(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(path, json + "\n").map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+}
+
+/// Appends the items of `extra` that are not already in `list`.
+pub fn extend_unique(list: &mut Vec<String>, extra: &[String]) {
+    for item in extra {
+        if !list.contains(item) {
+            list.push(item.clone());
+        }
+    }
+}
+
+/// Plans the hyprland.conf update: the auto-generated section is replaced or
+/// appended, or a separate autostart file is written when there is no config yet.
+pub fn hyprland_config_write(config: &InstallConfig) -> Option<FileWrite> {
+    let exec_statements = crate::generate_exec_once_statements(config);
+    if exec_statements.is_empty() {
+        return None;
+    }
+
+    let config_path = match crate::get_hyprland_config_path() {
+        Some(path) => path,
+        None => {
+            eprintln!("⚠️  Could not determine config path");
+            return None;
+        }
+    };
+
+    // Fix ownership if running as root
+    let owner = if crate::check_root() { crate::get_username() } else { None };
+
+    let mut config_content = match fs::read_to_string(&config_path) {
+        Ok(content) => content,
+        Err(_) => {
+            // Create a separate autostart config file instead
+            let autostart_path = config_path.with_file_name("hyprland-autostart.conf");
+            let autostart_content = format!(
+                "# Auto-generated autostart configuration\n# Generated by hyprland-installer\n# Include this in your main hyprland.conf with: source = ~/.config/hypr/hyprland-autostart.conf\n\n{}\n",
+                exec_statements.join("\n")
+            );
+
+            return Some(FileWrite {
+                path: autostart_path,
+                content: autostart_content,
+                backup: None,
+                owner,
+                description: format!("exec-once statements (no hyprland.conf at {})", config_path.display()),
+                note: Some("To use these settings, add this line to your hyprland.conf:\n   source = ~/.config/hypr/hyprland-autostart.conf".to_string()),
+            });
+        }
+    };
+
+    // Check for existing exec-once section; a stray END marker before START does not count
+    let section = config_content.find(MARKER_START).and_then(|start_idx| {
+        let end_idx = config_content[start_idx..].find(MARKER_END)? + start_idx + MARKER_END.len();
+        Some(start_idx..end_idx)
+    });
+    if let Some(section) = section {
+        // Replace existing auto-generated section
+        let new_section = format!(
+            "{}\n{}\n{}",
+            MARKER_START,
+            exec_statements.join("\n"),
+            MARKER_END
+        );
+
+        config_content.replace_range(section, &new_section);
+    } else {
+        // Add new auto-generated section
+        config_content.push_str("\n\n");
+        config_content.push_str(MARKER_START);
+        config_content.push('\n');
+        config_content.push_str(&exec_statements.join("\n"));
+        config_content.push('\n');
+        config_content.push_str(MARKER_END);
+        config_content.push('\n');
+    }
+
+    Some(FileWrite {
+        backup: Some(config_path.with_extension("conf.backup")),
+        path: config_path,
+        content: config_content,
+        owner,
+        description: "auto-generated exec-once section".to_string(),
+        note: None,
+    })
+}
@@ -0,0 +1,68 @@
+/// How one phase of an installation ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Succeeded,
+    /// Not needed or not possible, with the reason
+    Skipped(String),
+    /// With what went wrong
+    Failed(String),
+}
+
+impl From<Result<(), String>> for Outcome {
+    fn from(result: Result<(), String>) -> Self {
+        match result {
+            Ok(()) => Outcome::Succeeded,
+            Err(reason) => Outcome::Failed(reason),
+        }
+    }
+}
+
+/// The outcome of every phase of an installation, in the order they ran.
+#[derive(Debug, Default)]
+pub struct Report {
+    phases: Vec<(String, Outcome)>,
+}
+
+impl Report {
+    pub fn add(&mut self, phase: impl Into<String>, outcome: impl Into<Outcome>) {
+        self.phases.push((phase.into(), outcome.into()));
+    }
+
+    /// Records `phase` as skipped for `reason`.
+    pub fn skip(&mut self, phase: impl Into<String>, reason: &str) {
+        self.add(phase, Outcome::Skipped(reason.to_string()));
+    }
+
+    fn count(&self, matches: fn(&Outcome) -> bool) -> usize {
+        self.phases.iter().filter(|(_, outcome)| matches(outcome)).count()
+    }
+
+    pub fn failures(&self) -> usize {
+        self.count(|o| matches!(o, Outcome::Failed(_)))
+    }
+
+    pub fn has_failures(&self) -> bool {
+        self.failures() > 0
+    }
+
+    /// Prints a table of every phase with its outcome and the reason for
+    /// anything that failed or was skipped.
+    pub fn print(&self, title: &str) {
+        println!("\n═══ {} ═══\n", title);
+
+        for (phase, outcome) in &self.phases {
+            match outcome {
+                Outcome::Succeeded => println!(" ✓  {:<10} {}", "Succeeded", phase),
+                Outcome::Skipped(reason) => println!(" ─  {:<10} {}  ({})", "Skipped", phase, reason),
+                Outcome::Failed(reason) => println!(" ❌ {:<10} {}  ({})", "Failed", phase, reason),
+            }
+        }
+
+        println!(
+            "\n {} succeeded, {} failed, {} skipped",
+            self.count(|o| *o == Outcome::Succeeded),
+            self.failures(),
+            self.count(|o| matches!(o, Outcome::Skipped(_)))
+        );
+    }
+}
@@ -0,0 +1,364 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::bootloader;
+use crate::logfile;
+use crate::pacman;
+use crate::report::Report;
+use crate::runner::{run_checked, runner, Cmd};
+
+/// Where the installer keeps what it needs across runs.
+pub const STATE_DIR: &str = "/var/lib/hyprland-installer";
+
+/// What one installation changed, so it can be undone with `rollback`.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct RunRecord {
+    /// Seconds since the epoch when the run started; also its directory name
+    pub id: u64,
+    /// Explicitly installed packages (`pacman -Qqe`) before the run;
+    /// `None` if pacman could not be queried
+    pub packages_before: Option<Vec<String>>,
+    /// Enabled systemd units before the run
+    pub services_before: Option<Vec<String>>,
+    pub added_packages: Vec<String>,
+    pub removed_packages: Vec<String>,
+    pub enabled_services: Vec<String>,
+    pub disabled_services: Vec<String>,
+    pub files: Vec<FileRecord>,
+    #[serde(default)]
+    pub rolled_back: bool,
+}
+
+/// A file the run wrote, and where its previous content was saved.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileRecord {
+    pub path: PathBuf,
+    /// Copy of the file before the run; `None` if the run created it
+    pub saved: Option<PathBuf>,
+}
+
+static CURRENT: Mutex<Option<RunRecord>> = Mutex::new(None);
+
+fn runs_dir() -> PathBuf {
+    Path::new(STATE_DIR).join("runs")
+}
+
+fn run_dir(id: u64) -> PathBuf {
+    runs_dir().join(id.to_string())
+}
+
+/// Explicitly installed packages, one name per line of `pacman -Qqe`.
+fn explicit_packages() -> Option<Vec<String>> {
+    query_lines(&Cmd::new("pacman").arg("-Qqe"))
+}
+
+/// Enabled system units, from `systemctl list-unit-files`.
+pub fn enabled_services() -> Option<Vec<String>> {
+    let cmd = Cmd::new("systemctl").args(["list-unit-files", "--state=enabled", "--no-legend", "--plain"]);
+    let lines = query_lines(&cmd)?;
+    Some(lines.iter().filter_map(|line| line.split_whitespace().next().map(str::to_string)).collect())
+}
+
+/// The non-empty output lines of `cmd`, or `None` if it failed. A failed
+/// snapshot must not look like an empty one, or the diff would be wrong.
+fn query_lines(cmd: &Cmd) -> Option<Vec<String>> {
+    match runner().query(cmd) {
+        Ok(output) if output.status.success() => Some(
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .map(|l| l.trim().to_string())
+                .filter(|l| !l.is_empty())
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+/// `(after - before, before - after)`, or nothing if either snapshot failed.
+fn diff(before: &Option<Vec<String>>, after: &Option<Vec<String>>) -> (Vec<String>, Vec<String>) {
+    match (before, after) {
+        (Some(before), Some(after)) => (
+            after.iter().filter(|item| !before.contains(item)).cloned().collect(),
+            before.iter().filter(|item| !after.contains(item)).cloned().collect(),
+        ),
+        _ => (Vec::new(), Vec::new()),
+    }
+}
+
+/// Snapshots the installed packages and enabled services before a run.
+pub fn start() {
+    let id = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let record = RunRecord {
+        id,
+        packages_before: explicit_packages(),
+        services_before: enabled_services(),
+        ..Default::default()
+    };
+
+    if let Ok(mut current) = CURRENT.lock() {
+        *current = Some(record);
+    }
+}
+
+/// The record of the run in progress, for checkpointing.
+pub fn snapshot() -> Option<RunRecord> {
+    CURRENT.lock().ok()?.clone()
+}
+
+/// Continues recording an interrupted run instead of starting a new one,
+/// so its rollback also covers what happened before the interruption.
+pub fn resume(record: RunRecord) {
+    if let Ok(mut current) = CURRENT.lock() {
+        *current = Some(record);
+    }
+}
+
+/// Saves a copy of `path` before the run overwrites it. Only the first write
+/// of a file is recorded, and an existing copy is never replaced, so
+/// rollback restores the state before the run even after a resume.
+pub fn record_file(path: &Path) {
+    let mut current = match CURRENT.lock() {
+        Ok(current) => current,
+        Err(_) => return,
+    };
+    let record = match current.as_mut() {
+        Some(record) => record,
+        None => return,
+    };
+    if record.files.iter().any(|f| f.path == path) {
+        return;
+    }
+
+    let relative = path.strip_prefix("/").unwrap_or(path);
+    let copy = run_dir(record.id).join("files").join(relative);
+    let saved = if copy.exists() {
+        // Saved before an interruption; the file itself may already hold
+        // this run's changes, so the copy must not be replaced
+        Some(copy)
+    } else if path.exists() {
+        let copied = copy
+            .parent()
+            .map(fs::create_dir_all)
+            .unwrap_or(Ok(()))
+            .and_then(|_| fs::copy(path, &copy));
+        match copied {
+            Ok(_) => Some(copy),
+            Err(e) => {
+                eprintln!("⚠️  Could not save {} for rollback: {}", path.display(), e);
+                return;
+            }
+        }
+    } else {
+        None
+    };
+
+    record.files.push(FileRecord { path: path.to_path_buf(), saved });
+}
+
+/// Diffs packages and services against the snapshot and saves the record.
+/// Returns the run id, or `None` if nothing was recorded.
+pub fn finish() -> Option<u64> {
+    let mut record = CURRENT.lock().ok()?.take()?;
+
+    (record.added_packages, record.removed_packages) = diff(&record.packages_before, &explicit_packages());
+    (record.enabled_services, record.disabled_services) = diff(&record.services_before, &enabled_services());
+
+    match save(&record) {
+        Ok(_) => Some(record.id),
+        Err(e) => {
+            eprintln!("⚠️  Could not save the rollback record: {}", e);
+            None
+        }
+    }
+}
+
+fn save(record: &RunRecord) -> Result<(), String> {
+    let dir = run_dir(record.id);
+    fs::create_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    let json = serde_json::to_string_pretty(record).map_err(|e| e.to_string())?;
+    fs::write(dir.join("run.json"), json + "\n").map_err(|e| e.to_string())
+}
+
+/// The ids of all recorded runs, newest first.
+fn run_ids() -> Result<Vec<u64>, String> {
+    let mut ids: Vec<u64> = fs::read_dir(runs_dir())
+        .map_err(|_| "No recorded runs found".to_string())?
+        .filter_map(|e| e.ok())
+        .filter_map(|e| e.file_name().to_string_lossy().parse().ok())
+        .collect();
+    ids.sort_unstable_by(|a, b| b.cmp(a));
+    Ok(ids)
+}
+
+fn read_record(id: u64) -> Result<RunRecord, String> {
+    let path = run_dir(id).join("run.json");
+    let content = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    serde_json::from_str(&content).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Loads the record of run `id`, or of the latest run that was not rolled back.
+pub fn load(id: Option<u64>) -> Result<RunRecord, String> {
+    let ids = match id {
+        Some(id) => vec![id],
+        None => run_ids()?,
+    };
+
+    for id in ids {
+        let record = read_record(id)?;
+        if !record.rolled_back {
+            return Ok(record);
+        }
+    }
+    Err("No run left to roll back".to_string())
+}
+
+/// Every run that was not rolled back, oldest first. Unreadable records
+/// are reported and left out.
+pub fn records() -> Vec<RunRecord> {
+    let mut records = Vec::new();
+    for id in run_ids().unwrap_or_default().into_iter().rev() {
+        match read_record(id) {
+            Ok(record) if !record.rolled_back => records.push(record),
+            Ok(_) => {}
+            Err(e) => eprintln!("⚠️  {}", e),
+        }
+    }
+    records
+}
+
+impl RunRecord {
+    pub fn print(&self) {
+        println!(" Run {}", self.id);
+
+        let sections = [
+            ("Packages to remove", &self.added_packages),
+            ("Packages to reinstall", &self.removed_packages),
+            ("Services to disable", &self.enabled_services),
+            ("Services to re-enable", &self.disabled_services),
+        ];
+        for (title, items) in sections {
+            if !items.is_empty() {
+                println!("\n {}:", title);
+                for item in items {
+                    println!("   - {}", item);
+                }
+            }
+        }
+
+        if !self.files.is_empty() {
+            println!("\n Files to restore:");
+            for file in &self.files {
+                match file.saved {
+                    Some(_) => println!("   - {}", file.path.display()),
+                    None => println!("   - {}  (created by the run, will be deleted)", file.path.display()),
+                }
+            }
+        }
+    }
+
+    /// Undoes the run: services first (removing a package also removes its
+    /// unit files), then packages, then files, then the commands that
+    /// rebuild boot files from the restored configs. The run is only marked
+    /// as rolled back when every step succeeded.
+    pub fn roll_back(&mut self, dry_run: bool) -> Report {
+        let mut report = Report::default();
+
+        // Services no longer enabled (e.g. after an earlier, partly failed rollback) are skipped
+        let enabled = enabled_services();
+        for service in &self.enabled_services {
+            if enabled.as_ref().is_some_and(|enabled| !enabled.contains(service)) {
+                continue;
+            }
+            report.add(format!("Disable {}", service), run(Cmd::new("systemctl").arg("disable").arg(service)));
+        }
+        for service in &self.disabled_services {
+            report.add(format!("Re-enable {}", service), run(Cmd::new("systemctl").arg("enable").arg(service)));
+        }
+
+        // Packages already gone are skipped for the same reason
+        let db = pacman::local_db();
+        let added: Vec<&String> = self.added_packages.iter().filter(|p| db.is_empty() || db.get(p).is_some()).collect();
+        if !added.is_empty() {
+            println!("\n Removing added packages...");
+            report.add("Remove added packages", run(Cmd::new("pacman").args(["-Rns", "--noconfirm"]).args(added)));
+        }
+        if !self.removed_packages.is_empty() {
+            println!("\n Reinstalling removed packages...");
+            report.add(
+                "Reinstall removed packages",
+                run(Cmd::new("pacman").args(["-S", "--needed", "--noconfirm"]).args(&self.removed_packages)),
+            );
+        }
+
+        for file in self.files.iter().rev() {
+            report.add(format!("Restore {}", file.path.display()), restore_file(file, dry_run));
+        }
+
+        let paths: Vec<&Path> = self.files.iter().map(|f| f.path.as_path()).collect();
+        for command in bootloader::regeneration_commands(&paths) {
+            println!("\n {}...", command.description);
+            report.add(command.description.clone(), run(Cmd::new(&command.argv[0]).args(&command.argv[1..])));
+        }
+
+        if !dry_run && !report.has_failures() {
+            report.add("Mark the run as rolled back", self.mark_rolled_back());
+        }
+        report
+    }
+
+    /// Records that the run was undone, so `rollback` moves on to the one
+    /// before it and `uninstall` no longer counts it.
+    pub fn mark_rolled_back(&mut self) -> Result<(), String> {
+        self.rolled_back = true;
+        save(self).map_err(|e| {
+            eprintln!("⚠️  Could not mark run {} as rolled back: {}", self.id, e);
+            e
+        })
+    }
+}
+
+fn run(cmd: Cmd) -> Result<(), String> {
+    match run_checked(&cmd) {
+        Ok(()) => {
+            logfile::record(&format!("Rollback: {}", cmd));
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("⚠️  Failed: {}", cmd);
+            Err(e)
+        }
+    }
+}
+
+/// Puts back the saved copy of a file, or deletes it if the run created it.
+pub fn restore_file(file: &FileRecord, dry_run: bool) -> Result<(), String> {
+    let result = match file.saved {
+        Some(ref saved) if dry_run => {
+            println!("   Would restore {} from {}", file.path.display(), saved.display());
+            return Ok(());
+        }
+        None if dry_run => {
+            println!("   Would delete {}", file.path.display());
+            return Ok(());
+        }
+        Some(ref saved) => fs::copy(saved, &file.path).map(|_| "Restored"),
+        // Already gone is as good as deleted
+        None if !file.path.exists() => Ok("Deleted"),
+        None => fs::remove_file(&file.path).map(|_| "Deleted"),
+    };
+
+    match result {
+        Ok(action) => {
+            println!("✓ {} {}", action, file.path.display());
+            logfile::record(&format!("Rollback: {} {}", action, file.path.display()));
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("⚠️  Failed to restore {}: {}", file.path.display(), e);
+            Err(e.to_string())
+        }
+    }
+}
@@ -0,0 +1,293 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::process::ExitStatusExt;
+use std::path::PathBuf;
+use std::process::{Command, ExitStatus, Output, Stdio};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::Instant;
+
+use crate::cli::options;
+use crate::logfile::{self, CommandEntry};
+
+/// A command line to execute, optionally in a specific working directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cmd {
+    pub program: String,
+    pub args: Vec<String>,
+    pub cwd: Option<PathBuf>,
+}
+
+impl Cmd {
+    pub fn new(program: &str) -> Self {
+        Self {
+            program: program.to_string(),
+            args: Vec::new(),
+            cwd: None,
+        }
+    }
+
+    pub fn arg(mut self, arg: impl AsRef<str>) -> Self {
+        self.args.push(arg.as_ref().to_string());
+        self
+    }
+
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.args.extend(args.into_iter().map(|a| a.as_ref().to_string()));
+        self
+    }
+
+    /// Runs the command as `user` through sudo.
+    pub fn as_user(user: &str, program: &str) -> Self {
+        Cmd::new("sudo").arg("-u").arg(user).arg(program)
+    }
+
+    pub fn current_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.cwd = Some(dir.into());
+        self
+    }
+
+    /// The user the command runs as: the target of `sudo -u`, otherwise
+    /// whoever runs the installer.
+    fn user(&self) -> String {
+        match self.args.as_slice() {
+            [flag, user, ..] if self.program == "sudo" && flag == "-u" => user.clone(),
+            _ => std::env::var("USER")
+                .or_else(|_| std::env::var("LOGNAME"))
+                .or_else(|_| fs::metadata("/proc/self").map(|m| format!("uid {}", m.uid())))
+                .unwrap_or_else(|_| "unknown".to_string()),
+        }
+    }
+
+    /// The argv the way it would be typed into a shell.
+    fn command_line(&self) -> String {
+        let mut line = shell_quote(&self.program);
+        for arg in &self.args {
+            line.push(' ');
+            line.push_str(&shell_quote(arg));
+        }
+        line
+    }
+
+    /// Logs the command with its outcome and captured output.
+    fn log(&self, result: &io::Result<ExitStatus>, started: Instant, stdout: &[u8], stderr: &[u8]) {
+        let cwd = match self.cwd {
+            Some(ref cwd) => cwd.clone(),
+            None => std::env::current_dir().unwrap_or_default(),
+        };
+        let result = match result {
+            Ok(status) => match (status.code(), status.signal()) {
+                (Some(code), _) => format!("exit: {}", code),
+                (None, Some(signal)) => format!("killed by signal {}", signal),
+                (None, None) => "exit: unknown".to_string(),
+            },
+            Err(e) => format!("failed to start: {}", e),
+        };
+
+        logfile::record_command(&CommandEntry {
+            command: self.command_line(),
+            cwd: &cwd,
+            user: &self.user(),
+            result,
+            duration: started.elapsed(),
+            stdout,
+            stderr,
+        });
+    }
+
+    fn to_command(&self) -> Command {
+        let mut command = Command::new(&self.program);
+        command.args(&self.args);
+        if let Some(ref cwd) = self.cwd {
+            command.current_dir(cwd);
+        }
+        command
+    }
+}
+
+impl fmt::Display for Cmd {
+    /// Formats the argv the way it would be typed into a shell.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.command_line())?;
+        if let Some(ref cwd) = self.cwd {
+            write!(f, "  (in {})", cwd.display())?;
+        }
+        Ok(())
+    }
+}
+
+fn shell_quote(arg: &str) -> String {
+    let safe = !arg.is_empty()
+        && arg.chars().all(|c| c.is_ascii_alphanumeric() || "-_./:=@+,%".contains(c));
+    if safe {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+/// Every external command the installer runs goes through a `CommandRunner`.
+pub trait CommandRunner: Sync {
+    /// Runs a command that changes the system, with output going to the terminal.
+    fn run(&self, cmd: &Cmd) -> io::Result<ExitStatus>;
+
+    /// Runs a read-only command (`which`, `id`, ...) and captures its output.
+    fn query(&self, cmd: &Cmd) -> io::Result<Output>;
+
+    /// Runs `cmd` and turns a failure to start or a non-zero exit status
+    /// into an error message.
+    fn run_checked(&self, cmd: &Cmd) -> Result<(), String> {
+        match self.run(cmd) {
+            Ok(status) if status.success() => Ok(()),
+            Ok(status) => Err(match status.code() {
+                Some(code) => format!("{} exited with status {}", cmd.program, code),
+                None => format!("{} was killed", cmd.program),
+            }),
+            Err(e) => Err(format!("could not run {}: {}", cmd.program, e)),
+        }
+    }
+}
+
+/// Copies everything from `source` to `sink` as it arrives and returns a
+/// copy of it, so output can be shown live and still be logged.
+fn tee(mut source: impl Read, mut sink: impl Write) -> Vec<u8> {
+    let mut captured = Vec::new();
+    let mut buffer = [0u8; 4096];
+    while let Ok(n) = source.read(&mut buffer) {
+        if n == 0 {
+            break;
+        }
+        let _ = sink.write_all(&buffer[..n]);
+        let _ = sink.flush();
+        captured.extend_from_slice(&buffer[..n]);
+    }
+    captured
+}
+
+/// Executes commands for real and logs each one with its output.
+pub struct RealRunner;
+
+impl CommandRunner for RealRunner {
+    fn run(&self, cmd: &Cmd) -> io::Result<ExitStatus> {
+        let started = Instant::now();
+        let child = cmd.to_command().stdout(Stdio::piped()).stderr(Stdio::piped()).spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(e) => {
+                let result = Err(e);
+                cmd.log(&result, started, &[], &[]);
+                return result;
+            }
+        };
+
+        // Both pipes are drained at once, so neither can fill up and stall the command
+        let stdout = child.stdout.take().map(|out| thread::spawn(move || tee(out, io::stdout())));
+        let stderr = child.stderr.take().map(|err| thread::spawn(move || tee(err, io::stderr())));
+        let result = child.wait();
+        let stdout = stdout.and_then(|t| t.join().ok()).unwrap_or_default();
+        let stderr = stderr.and_then(|t| t.join().ok()).unwrap_or_default();
+
+        cmd.log(&result, started, &stdout, &stderr);
+        result
+    }
+
+    fn query(&self, cmd: &Cmd) -> io::Result<Output> {
+        let started = Instant::now();
+        let output = cmd.to_command().output();
+        match output {
+            Ok(ref output) => cmd.log(&Ok(output.status), started, &output.stdout, &output.stderr),
+            Err(ref e) => cmd.log(&Err(io::Error::new(e.kind(), e.to_string())), started, &[], &[]),
+        }
+        output
+    }
+}
+
+/// Records the commands it is asked to run instead of running them, and
+/// reports them as successful unless told to fail them. Read-only queries
+/// still execute, so a dry run sees the real state of the system.
+#[derive(Default)]
+pub struct RecordingRunner {
+    commands: Mutex<Vec<Cmd>>,
+    /// A command with any of these arguments exits with status 1
+    failures: Vec<String>,
+}
+
+impl RecordingRunner {
+    /// A runner that fails every command naming one of `args`, e.g. the
+    /// packages a test wants pacman to reject.
+    #[cfg(test)]
+    pub fn failing_on(args: &[&str]) -> Self {
+        RecordingRunner {
+            failures: args.iter().map(|a| a.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// The commands that would have been executed, in order.
+    pub fn commands(&self) -> Vec<Cmd> {
+        self.commands.lock().map(|c| c.clone()).unwrap_or_default()
+    }
+}
+
+impl CommandRunner for RecordingRunner {
+    fn run(&self, cmd: &Cmd) -> io::Result<ExitStatus> {
+        println!("   $ {}", cmd);
+        logfile::record(&format!("Would run: {}", cmd));
+        if let Ok(mut commands) = self.commands.lock() {
+            commands.push(cmd.clone());
+        }
+        let fails = cmd.args.iter().any(|arg| self.failures.contains(arg));
+        // A wait status holds the exit code in its second byte
+        Ok(ExitStatus::from_raw(if fails { 1 << 8 } else { 0 }))
+    }
+
+    fn query(&self, cmd: &Cmd) -> io::Result<Output> {
+        RealRunner.query(cmd)
+    }
+}
+
+static REAL: RealRunner = RealRunner;
+static RECORDER: OnceLock<RecordingRunner> = OnceLock::new();
+
+/// The runner for this run: a `RecordingRunner` in dry run mode, otherwise a `RealRunner`.
+pub fn runner() -> &'static dyn CommandRunner {
+    if options().dry_run {
+        RECORDER.get_or_init(RecordingRunner::default)
+    } else {
+        &REAL
+    }
+}
+
+/// Runs `cmd` with this run's runner; see `CommandRunner::run_checked`.
+pub fn run_checked(cmd: &Cmd) -> Result<(), String> {
+    runner().run_checked(cmd)
+}
+
+/// Commands recorded so far by the dry run runner.
+pub fn recorded_commands() -> Vec<Cmd> {
+    RECORDER.get().map(|r| r.commands()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recording_runner_records_and_fails_on_request() {
+        let runner = RecordingRunner::failing_on(&["bad"]);
+
+        assert_eq!(runner.run_checked(&Cmd::new("pacman").args(["-S", "good"])), Ok(()));
+        assert_eq!(
+            runner.run_checked(&Cmd::new("pacman").args(["-S", "good", "bad"])),
+            Err("pacman exited with status 1".to_string())
+        );
+        assert_eq!(runner.commands().len(), 2);
+        assert_eq!(runner.commands()[1].args, vec!["-S", "good", "bad"]);
+    }
+}
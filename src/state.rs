@@ -0,0 +1,110 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::answers::AnswerFile;
+use crate::catalog::{catalog, StepKind};
+use crate::plan::InstallPlan;
+use crate::rollback::STATE_DIR;
+use crate::InstallConfig;
+
+const STATE_FILE: &str = "state.json";
+
+/// The setup applied by the last install, so a re-run can start from it.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct State {
+    /// Every step as it was answered
+    pub answers: AnswerFile,
+    /// The plan that was carried out
+    pub plan: InstallPlan,
+}
+
+pub fn path() -> PathBuf {
+    Path::new(STATE_DIR).join(STATE_FILE)
+}
+
+/// Loads the state of the last install. A state file that no longer fits
+/// the catalog (e.g. a removed components.d entry) is reported and ignored.
+pub fn load() -> Option<State> {
+    let path = path();
+    let content = fs::read_to_string(&path).ok()?;
+
+    let state: State = match serde_json::from_str(&content) {
+        Ok(state) => state,
+        Err(e) => {
+            eprintln!("⚠️  Ignoring {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    if let Err(e) = state.answers.validate() {
+        eprintln!("⚠️  Ignoring {}: {}", path.display(), e);
+        return None;
+    }
+    Some(state)
+}
+
+/// Records `config` and `plan` as the current setup.
+pub fn save(config: &InstallConfig, plan: &InstallPlan) -> Result<(), String> {
+    let state = State { answers: AnswerFile::from_config(config), plan: plan.clone() };
+    let path = path();
+
+    fs::create_dir_all(STATE_DIR).map_err(|e| format!("{}: {}", STATE_DIR, e))?;
+    let json = serde_json::to_string_pretty(&state).map_err(|e| e.to_string())?;
+    fs::write(&path, json + "\n").map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+impl State {
+    /// The previous answers as a config, for preselecting the prompts.
+    pub fn config(&self) -> InstallConfig {
+        self.answers.config()
+    }
+
+    /// Lists the steps answered differently from the last install, and the
+    /// packages that come and go with them.
+    pub fn print_changes(&self, config: &InstallConfig, plan: &InstallPlan) {
+        let previous = self.config();
+
+        println!(" Changes from the current setup:");
+
+        let mut changed = false;
+        for step in &catalog().steps {
+            let before = previous.selection(&step.key);
+            let after = config.selection(&step.key);
+            if before != after {
+                println!("   {:<22} {} → {}", format!("{}:", step.name), describe(step.kind, &before), describe(step.kind, &after));
+                changed = true;
+            }
+        }
+        if !changed {
+            println!("   (no step answered differently)");
+        }
+
+        let packages = |plan: &InstallPlan| -> Vec<String> {
+            plan.repo_packages.iter().chain(&plan.aur_packages).cloned().collect()
+        };
+        let (old, new) = (packages(&self.plan), packages(plan));
+
+        let added: Vec<&String> = new.iter().filter(|p| !old.contains(p)).collect();
+        let dropped: Vec<&String> = old.iter().filter(|p| !new.contains(p)).collect();
+        if !added.is_empty() {
+            println!("\n   New packages: {}", join(&added));
+        }
+        if !dropped.is_empty() {
+            println!("   No longer selected (left installed): {}", join(&dropped));
+        }
+        println!();
+    }
+}
+
+fn describe(kind: StepKind, selection: &[String]) -> String {
+    match kind {
+        StepKind::Toggle if selection.is_empty() => "No".to_string(),
+        StepKind::Toggle => "Yes".to_string(),
+        _ if selection.is_empty() => "None".to_string(),
+        _ => selection.join(", "),
+    }
+}
+
+fn join(items: &[&String]) -> String {
+    items.iter().map(|s| s.as_str()).collect::<Vec<_>>().join(", ")
+}
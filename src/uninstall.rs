@@ -0,0 +1,160 @@
+use crate::catalog::catalog;
+use crate::pacman::local_db;
+use crate::plan::extend_unique;
+use crate::rollback::{self, FileRecord, RunRecord};
+use crate::state;
+
+/// Where the lists of a `Removal` come from, from most to least exact.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// The rollback records of the installs
+    Records,
+    /// The plan of the last install in `state.json`; it may name packages
+    /// that were installed before the installer ran
+    State,
+    /// Nothing recorded: the catalog's packages found on this system, which
+    /// are only listed and never removed
+    #[default]
+    Catalog,
+}
+
+/// The packages, services and files `uninstall` would remove or restore.
+#[derive(Debug, Default)]
+pub struct Removal {
+    pub source: Source,
+    pub packages: Vec<String>,
+    /// Packages left installed, with the reason
+    pub kept: Vec<(String, String)>,
+    pub services: Vec<String>,
+    /// System files the installs wrote, as saved by the first run that
+    /// wrote each one
+    pub files: Vec<FileRecord>,
+    /// The runs this removal undoes, to be marked as rolled back
+    pub runs: Vec<RunRecord>,
+}
+
+impl Removal {
+    /// Works out what to remove from the recorded runs, from the saved
+    /// state when there are none, or from the catalog as a last resort.
+    /// Only packages that are still installed and not needed by anything
+    /// else are removed.
+    pub fn plan() -> Self {
+        let runs = rollback::records();
+
+        let mut candidates: Vec<String> = Vec::new();
+        let mut services: Vec<String> = Vec::new();
+        let mut files: Vec<FileRecord> = Vec::new();
+        let source = if !runs.is_empty() {
+            for run in &runs {
+                extend_unique(&mut candidates, &run.added_packages);
+                extend_unique(&mut services, &run.enabled_services);
+            }
+            // The user's own config is stripped rather than restored, and
+            // the state file is deleted anyway
+            let user_config = crate::get_user_config_dir();
+            for file in runs.iter().flat_map(|run| &run.files) {
+                let managed = file.path != state::path() && !user_config.as_ref().is_some_and(|dir| file.path.starts_with(dir));
+                if managed && !files.iter().any(|f| f.path == file.path) {
+                    files.push(file.clone());
+                }
+            }
+            Source::Records
+        } else if let Some(state) = state::load() {
+            extend_unique(&mut candidates, &state.plan.repo_packages);
+            extend_unique(&mut candidates, &state.plan.aur_packages);
+            extend_unique(&mut services, &state.plan.services);
+            Source::State
+        } else {
+            for component in &catalog().components {
+                extend_unique(&mut candidates, &component.packages);
+                extend_unique(&mut candidates, &component.aur_packages);
+                extend_unique(&mut services, &component.services);
+            }
+            Source::Catalog
+        };
+
+        let db = local_db();
+        candidates.retain(|name| db.get(name).is_some());
+
+        // Keep anything another remaining package depends on; keeping one
+        // package can make its own dependencies needed again, so repeat
+        let mut kept: Vec<(String, String)> = Vec::new();
+        loop {
+            let blocked: Vec<(String, String)> = candidates
+                .iter()
+                .filter_map(|name| {
+                    let dependents: Vec<String> = db
+                        .required_by(name)
+                        .into_iter()
+                        .filter(|p| !candidates.contains(&p.name))
+                        .map(|p| p.name.clone())
+                        .collect();
+                    (!dependents.is_empty()).then(|| (name.clone(), format!("required by {}", dependents.join(", "))))
+                })
+                .collect();
+            if blocked.is_empty() {
+                break;
+            }
+            candidates.retain(|name| !blocked.iter().any(|(b, _)| b == name));
+            kept.extend(blocked);
+        }
+
+        if let Some(enabled) = rollback::enabled_services() {
+            services.retain(|service| enabled.contains(service));
+        }
+
+        Removal { source, packages: candidates, kept, services, files, runs }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.packages.is_empty() && self.services.is_empty() && self.files.is_empty()
+    }
+
+    /// Whether the packages and services may be removed. Without any
+    /// record of an install they are only listed.
+    pub fn can_remove(&self) -> bool {
+        self.source != Source::Catalog
+    }
+
+    pub fn print(&self) {
+        match self.source {
+            Source::Records => {}
+            Source::State => {
+                println!("⚠️  No install records found; these come from the last install's plan in {}.", state::path().display());
+                println!("   Some of them may have been installed before the installer ran.\n");
+            }
+            Source::Catalog => {
+                println!("⚠️  No install records or saved state found, so nothing is known to come from the installer.");
+                println!("   These catalog packages and services are on this system; remove the ones you no longer need by hand.\n");
+            }
+        }
+
+        if !self.packages.is_empty() {
+            println!(" {}:", if self.can_remove() { "Packages to remove" } else { "Catalog packages installed" });
+            for package in &self.packages {
+                println!("   - {}", package);
+            }
+        }
+        if !self.kept.is_empty() {
+            println!(" Packages left installed:");
+            for (package, reason) in &self.kept {
+                println!("   - {}  ({})", package, reason);
+            }
+        }
+        if !self.services.is_empty() {
+            println!(" {}:", if self.can_remove() { "Services to disable" } else { "Catalog services enabled" });
+            for service in &self.services {
+                println!("   - {}", service);
+            }
+        }
+        if !self.files.is_empty() {
+            println!(" Files to restore:");
+            for file in &self.files {
+                match file.saved {
+                    Some(_) => println!("   - {}", file.path.display()),
+                    None => println!("   - {}  (created by the installer, will be deleted)", file.path.display()),
+                }
+            }
+        }
+    }
+}